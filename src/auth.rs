@@ -0,0 +1,326 @@
+use std::{
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use color_eyre::{
+    Result,
+    eyre::{OptionExt, WrapErr, eyre},
+};
+use grammers_client::{
+    Client, Config as GrammersConfig, InitParams, SignInError, Update, grammers_tl_types as tl,
+    session::Session,
+    types::{PasswordToken, User},
+};
+use qrcode::{QrCode, render::unicode::Dense1x2};
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+use crate::{cli::LoginArgs, config::Profile, session::SessionFile};
+
+/// The datacenter grammers connects to when the session has no user yet.
+const DEFAULT_DC: i32 = 2;
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Not logged in, run `{} login` first", env!("CARGO_PKG_NAME"))]
+    NotAuthorized,
+}
+
+/// Connects to Telegram using the configured session file, creating it if needed.
+///
+/// Returns the session file as well, which is where the session should be saved.
+pub async fn connect(profile: &Profile) -> Result<(Client, SessionFile)> {
+    let session_path = profile.session_filename();
+
+    if let Some(session_dir) = session_path.parent()
+        && session_dir.to_string_lossy() != ""
+        && !session_dir.exists()
+    {
+        std::fs::create_dir_all(session_dir).wrap_err_with(|| {
+            format!(
+                "Failed to create session directory: {}",
+                session_dir.display()
+            )
+        })?;
+    }
+
+    let (session_file, session) =
+        SessionFile::open(profile).wrap_err("Failed to load or create session")?;
+
+    info!(
+        encrypted = session_file.is_encrypted(),
+        "Using session file: {}",
+        session_path.display()
+    );
+
+    let client = connect_with_session(profile, session).await?;
+    Ok((client, session_file))
+}
+
+pub async fn connect_with_session(profile: &Profile, session: Session) -> Result<Client> {
+    Client::connect(GrammersConfig {
+        api_id: profile.api_id(),
+        api_hash: profile.api_hash().to_string(),
+        session,
+        params: InitParams {
+            flood_sleep_threshold: profile.rate_limits().max_flood_wait.as_secs() as u32,
+            ..Default::default()
+        },
+    })
+    .await
+    .wrap_err("Failed to connect to Telegram")
+}
+
+pub async fn is_authorized(client: &Client) -> Result<bool> {
+    client
+        .is_authorized()
+        .await
+        .wrap_err("Failed to check authorization")
+}
+
+/// Interactively signs in and saves the session, unless already signed in.
+pub async fn login(profile: &Profile, args: LoginArgs) -> Result<()> {
+    let (client, session_file) = connect(profile).await?;
+
+    if is_authorized(&client).await? {
+        info!("Already logged in");
+        println!("Already logged in");
+        return Ok(());
+    }
+
+    let (client, user) = match args.qr {
+        true => login_with_qr(profile, client).await?,
+        false => {
+            let user = login_with_code(profile, &client).await?;
+            (client, user)
+        }
+    };
+
+    if let Err(err) = session_file
+        .save(client.session())
+        .wrap_err("Failed to save session")
+    {
+        client.sign_out().await.wrap_err("Failed to sign out")?;
+        return Err(err);
+    };
+
+    info!(
+        "Successfully signed in as {} (ID: {})",
+        user.username().unwrap_or("<no username>"),
+        user.id()
+    );
+    println!(
+        "Logged in as {}",
+        user.username().unwrap_or(user.first_name())
+    );
+
+    Ok(())
+}
+
+async fn login_with_code(profile: &Profile, client: &Client) -> Result<User> {
+    let phone_number = profile
+        .phone_number()
+        .ok_or_eyre("Phone number not provided, it is required unless logging in with --qr")?;
+
+    info!("Requesting token SMS");
+    let token = client
+        .request_login_code(phone_number)
+        .await
+        .wrap_err("Failed to request login code")?;
+
+    print!("Enter the code you received: ");
+    std::io::stdout().flush()?;
+    let mut code = String::new();
+    std::io::stdin().read_line(&mut code)?;
+
+    let code = code.trim().to_string();
+
+    match client.sign_in(&token, &code).await {
+        Ok(user) => Ok(user),
+        Err(SignInError::PasswordRequired(password_token)) => {
+            check_password(profile, client, password_token).await
+        }
+        Err(err) => Err(err).wrap_err("Failed to sign in"),
+    }
+}
+
+/// Signs in by showing a QR code to be scanned from an already logged in device.
+///
+/// Returns the client to keep using, which differs from the given one if the
+/// account lives in another datacenter.
+async fn login_with_qr(profile: &Profile, mut client: Client) -> Result<(Client, User)> {
+    // Where the client connected, which is only the default without a user
+    let mut dc_id = client
+        .session()
+        .get_user()
+        .map_or(DEFAULT_DC, |user| user.dc);
+    let mut request = QrRequest::Export;
+
+    loop {
+        let result = match request {
+            QrRequest::Export => {
+                client
+                    .invoke(&tl::functions::auth::ExportLoginToken {
+                        api_id: profile.api_id(),
+                        api_hash: profile.api_hash().to_string(),
+                        except_ids: Vec::new(),
+                    })
+                    .await
+            }
+            QrRequest::Import(ref token) => {
+                client
+                    .invoke(&tl::functions::auth::ImportLoginToken {
+                        token: token.clone(),
+                    })
+                    .await
+            }
+        };
+
+        let login_token = match result {
+            Ok(login_token) => login_token,
+            Err(err) if err.is("SESSION_PASSWORD_NEEDED") => {
+                let password = client
+                    .invoke(&tl::functions::account::GetPassword {})
+                    .await
+                    .wrap_err("Failed to get password information")?;
+                let password_token = PasswordToken::new(password.into());
+                let user = check_password(profile, &client, password_token).await?;
+                return Ok((client, user));
+            }
+            Err(err) => return Err(err).wrap_err("Failed to get login token"),
+        };
+
+        request = QrRequest::Export;
+
+        match login_token {
+            tl::enums::auth::LoginToken::Token(token) => {
+                print_qr(&token.token)?;
+                wait_for_qr_scan(&client, token.expires).await?;
+            }
+            tl::enums::auth::LoginToken::MigrateTo(migrate) => {
+                debug!(dc_id = migrate.dc_id, "Account is in another datacenter");
+                dc_id = migrate.dc_id;
+
+                // Not logged in yet, so the placeholder user only serves to make
+                // the new client connect to the account's datacenter
+                let session = client.session();
+                session.set_user(0, dc_id, false);
+                let session = Session::load(&session.save())
+                    .map_err(|err| eyre!("Failed to copy session: {:?}", err))?;
+                client = connect_with_session(profile, session).await?;
+                request = QrRequest::Import(migrate.token);
+            }
+            tl::enums::auth::LoginToken::Success(success) => {
+                let tl::enums::auth::Authorization::Authorization(authorization) =
+                    success.authorization
+                else {
+                    return Err(eyre!("Account needs to be signed up first"));
+                };
+
+                let user = User::from_raw(authorization.user);
+                client.session().set_user(user.id(), dc_id, false);
+                return Ok((client, user));
+            }
+        }
+    }
+}
+
+enum QrRequest {
+    Export,
+    Import(Vec<u8>),
+}
+
+fn print_qr(token: &[u8]) -> Result<()> {
+    let url = format!("tg://login?token={}", URL_SAFE_NO_PAD.encode(token));
+    let code = QrCode::new(url).wrap_err("Failed to create QR code")?;
+    // Inverted so that the code shows up on terminals with a dark background
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+
+    println!("{}", image);
+    println!("Scan the code above in Telegram under Settings > Devices > Link Desktop Device");
+
+    Ok(())
+}
+
+/// Waits until the login token has been accepted or `expires` (a unix timestamp) passes.
+async fn wait_for_qr_scan(client: &Client, expires: i32) -> Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let deadline = Instant::now() + Duration::from_secs((expires as i64 - now).max(1) as u64);
+
+    loop {
+        let update = match tokio::time::timeout_at(deadline, client.next_update()).await {
+            Ok(update) => update.wrap_err("Failed to get update")?,
+            Err(_) => {
+                debug!("Login token expired, requesting a new one");
+                return Ok(());
+            }
+        };
+
+        if let Update::Raw(tl::enums::Update::LoginToken) = update {
+            debug!("Login token accepted");
+            return Ok(());
+        }
+    }
+}
+
+async fn check_password(
+    profile: &Profile,
+    client: &Client,
+    password_token: PasswordToken,
+) -> Result<User> {
+    info!("Two-step verification is enabled, password required");
+    let password = match profile.password()? {
+        Some(password) => password,
+        None => prompt_password(password_token.hint())?,
+    };
+
+    client
+        .check_password(password_token, password)
+        .await
+        .wrap_err("Failed to check password")
+}
+
+/// Signs out of Telegram and deletes the session file.
+pub async fn logout(profile: &Profile) -> Result<()> {
+    let session_path = profile.session_filename();
+
+    if !session_path.exists() {
+        println!("Not logged in");
+        return Ok(());
+    }
+
+    let (client, session_file) = connect(profile).await?;
+
+    if is_authorized(&client).await? {
+        client.sign_out().await.wrap_err("Failed to sign out")?;
+        info!("Signed out");
+    }
+
+    session_file
+        .remove()
+        .wrap_err_with(|| format!("Failed to delete session file: {}", session_path.display()))?;
+
+    println!("Logged out");
+
+    Ok(())
+}
+
+/// Reads the two-step verification password from the terminal without echoing it.
+fn prompt_password(hint: Option<&str>) -> Result<String> {
+    let prompt = match hint {
+        Some(hint) if !hint.is_empty() => format!("Enter your password (hint: {}): ", hint),
+        _ => "Enter your password: ".to_string(),
+    };
+
+    rpassword::prompt_password(prompt).wrap_err("Failed to read password")
+}
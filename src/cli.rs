@@ -1,6 +1,6 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
-use clap::Parser;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, parser::ValueSource};
 
 use crate::logging::LogLevel;
 
@@ -13,11 +13,20 @@ const ENV_CONFIG: &str = "SHABBY_CONFIG";
 const ENV_API_ID: &str = "SHABBY_TG_API_ID";
 const ENV_API_HASH: &str = "SHABBY_TG_API_HASH";
 const ENV_PHONE_NUMBER: &str = "SHABBY_TG_PHONE_NUMBER";
+const ENV_PROFILE: &str = "SHABBY_PROFILE";
+const ENV_PASSWORD: &str = "SHABBY_TG_PASSWORD";
+const ENV_PASSWORD_FILE: &str = "SHABBY_TG_PASSWORD_FILE";
+const ENV_SESSION_PASSPHRASE: &str = "SHABBY_SESSION_PASSPHRASE";
+const ENV_SESSION_PASSPHRASE_FILE: &str = "SHABBY_SESSION_PASSPHRASE_FILE";
 const ENV_SESSION: &str = "SHABBY_SESSION";
+const ENV_STORAGE: &str = "SHABBY_STORAGE";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+
     #[command(flatten)]
     pub verbose: Verbosity,
 
@@ -42,6 +51,16 @@ pub struct Cli {
     )]
     pub config: Option<PathBuf>,
 
+    /// Selects which profiles from the configuration file to use, defaults to all.
+    #[arg(
+        short = 'P',
+        long = "profile",
+        env = ENV_PROFILE,
+        global = true,
+        value_delimiter = ',',
+    )]
+    pub profiles: Vec<String>,
+
     /// Specifies the API ID for Telegram.
     #[arg(
         short = 'i',
@@ -69,12 +88,133 @@ pub struct Cli {
     )]
     pub phone_number: Option<String>,
 
+    /// Specifies the two-step verification password for Telegram login.
+    ///
+    /// Prefer setting this through the environment or `--password-file`, as
+    /// command line arguments may be visible to other users.
+    #[arg(
+        long,
+        env = ENV_PASSWORD,
+        hide_env_values = true,
+        global = true,
+        conflicts_with = "password_file",
+    )]
+    pub password: Option<String>,
+
+    /// Specifies a file containing the two-step verification password.
+    #[arg(long, env = ENV_PASSWORD_FILE, global = true)]
+    pub password_file: Option<PathBuf>,
+
     /// Specifies the path to the session file.
     #[arg(short, long, env = ENV_SESSION, global = true)]
     pub session: Option<PathBuf>,
+
+    /// Specifies the passphrase to encrypt the session file with.
+    ///
+    /// Prefer setting this through the environment or `--session-passphrase-file`.
+    /// If neither is given, an encrypted session file prompts for its passphrase.
+    #[arg(
+        long,
+        env = ENV_SESSION_PASSPHRASE,
+        hide_env_values = true,
+        global = true,
+        conflicts_with = "session_passphrase_file",
+    )]
+    pub session_passphrase: Option<String>,
+
+    /// Specifies a file containing the passphrase to encrypt the session file with.
+    #[arg(long, env = ENV_SESSION_PASSPHRASE_FILE, global = true)]
+    pub session_passphrase_file: Option<PathBuf>,
+
+    /// Specifies the path to the storage database.
+    #[arg(long, env = ENV_STORAGE, global = true)]
+    pub storage: Option<PathBuf>,
+
+    /// Where each argument that was given got its value from.
+    #[arg(skip)]
+    sources: HashMap<String, ValueSource>,
+}
+
+#[derive(Subcommand, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CliCommand {
+    /// Interactively logs in to Telegram and saves the session.
+    Login(LoginArgs),
+
+    /// Logs out of Telegram and deletes the session file.
+    Logout,
+
+    /// Manages the session file.
+    #[command(subcommand)]
+    Session(SessionCommand),
+
+    /// Inspects the configuration.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Runs the bot, failing if not logged in (the default).
+    #[default]
+    Run,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionCommand {
+    /// Encrypts the session file with a passphrase.
+    Encrypt,
+
+    /// Decrypts the session file, storing it as plaintext.
+    Decrypt,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigCommand {
+    /// Validates the config file, reporting every invalid value in it.
+    Check,
+
+    /// Prints the effective config and where each value came from, with secrets redacted.
+    Dump,
+}
+
+#[derive(Args, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LoginArgs {
+    /// Log in by scanning a QR code from another device instead of with a phone number.
+    #[arg(long)]
+    pub qr: bool,
 }
 
 impl Cli {
+    /// Parses the arguments of the process, remembering where each value came from.
+    pub fn try_parse_with_sources() -> Result<Self, clap::Error> {
+        let matches = Self::command().try_get_matches()?;
+        let mut cli = Self::from_arg_matches(&matches)?;
+
+        cli.sources = Self::command()
+            .get_arguments()
+            .filter_map(|arg| {
+                let id = arg.get_id().as_str();
+                let source = matches.value_source(id)?;
+                Some((id.to_string(), source))
+            })
+            .collect();
+
+        Ok(cli)
+    }
+
+    /// Describes where the argument with the given ID got its value from, if it
+    /// was given on the command line or in the environment.
+    pub fn origin(&self, id: &str) -> Option<String> {
+        let command = Self::command();
+        let arg = command.get_arguments().find(|arg| arg.get_id() == id)?;
+
+        match self.sources.get(id)? {
+            ValueSource::CommandLine => Some(match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => format!("-{}", arg.get_short()?),
+            }),
+            ValueSource::EnvVariable => Some(format!("${}", arg.get_env()?.to_string_lossy())),
+            _ => None,
+        }
+    }
+
     pub fn log_level(&self) -> Option<LogLevel> {
         if let Some(ll) = self.log_level {
             return Some(ll);
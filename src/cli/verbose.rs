@@ -1,4 +1,4 @@
-use clap::{ArgAction, Args, arg};
+use clap::{ArgAction, Args};
 
 use indoc::indoc;
 
@@ -83,6 +83,7 @@ pub trait VerbosityLevel {
     }
 }
 
+#[cfg_attr(debug_assertions, allow(dead_code))]
 #[derive(Copy, Clone, Debug)]
 pub struct InfoLevel;
 
@@ -123,6 +124,7 @@ impl VerbosityLevel for InfoLevel {
     }
 }
 
+#[cfg_attr(not(debug_assertions), allow(dead_code))]
 #[derive(Copy, Clone, Debug)]
 pub struct DebugLevel;
 
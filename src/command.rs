@@ -1,103 +1,273 @@
-use clap::{Parser, Subcommand};
-use color_eyre::Result;
+use async_trait::async_trait;
+use clap::ArgMatches;
 use grammers_client::InputMessage;
+use tracing::debug;
 
-use crate::Context;
+use crate::{Context, storage::StorageError};
 
-use self::{case::CaseArgs, dice::DiceArgs};
+pub use self::{
+    alias::{AliasCommand, AliasStore},
+    builtin::{ChatIdCommand, MsgIdCommand, PingCommand, QuitCommand},
+    case::CaseCommand,
+    dice::{DiceCommand, DiceStatsCommand},
+    help::{HelpCommand, help_message},
+    note::{NoteCommand, expand_note_shorthand},
+    remind::{RemindCommand, ScheduleCommand},
+    roll::RollCommand,
+    text::TextCommand,
+};
 
+mod alias;
+mod builtin;
 mod case;
 mod dice;
+mod help;
+mod note;
+mod remind;
+mod roll;
+mod text;
 
-#[derive(Parser, Debug)]
-#[command()]
-pub struct BotCommand {
-    #[command(subcommand)]
-    pub action: BotAction,
-}
-
-#[derive(Subcommand, Debug)]
-pub enum BotAction {
-    Quit,
+/// A chat command that can be registered in a [`CommandRegistry`].
+#[async_trait]
+pub trait Command: Send + Sync {
+    /// The name used to invoke the command.
+    fn name(&self) -> &'static str;
 
-    Ping,
-
-    MsgId,
+    /// Alternative names the command can be invoked by.
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
 
-    ChatId,
+    /// Adds the command's arguments to its clap definition.
+    fn args(&self, command: clap::Command) -> clap::Command {
+        command
+    }
 
-    #[command(alias = "c")]
-    Case(CaseArgs),
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError>;
+}
 
-    Dice(DiceArgs),
+/// The set of commands that chat messages are dispatched to.
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: Vec<Box<dyn Command>>,
 }
 
 pub enum ActionResponse {
     Delete,
     Edit(InputMessage),
     Reply(InputMessage),
+    /// Deletes the command message and sends a new message in its place.
+    Replace(InputMessage),
 }
 
 pub struct ActionResult {
     pub quit: bool,
     pub response: Option<ActionResponse>,
+    /// Plain text output that can be piped into the next command.
+    pub text: Option<String>,
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum BotCommandError {
-    #[error("Missing required prefix for command")]
-    MissingPrefix,
+    #[error("Missing required prefix for command (expected one of: {})", .0.join(" "))]
+    MissingPrefix(Vec<String>),
 
     #[error("Failed to parse command")]
     ParseFailed,
 
+    #[error("Unknown command: {0}")]
+    UnknownCommand(String),
+
+    #[error("Alias expansion is nested too deeply: {0}")]
+    AliasRecursion(String),
+
+    #[error("Command does not produce output that can be piped: {0}")]
+    NoPipeOutput(String),
+
     #[error("Clap parsing error")]
     Clap(#[from] clap::Error),
 
     #[error("Grammers invocation failed: {0}")]
     Grammers(#[from] grammers_client::InvocationError),
+
+    #[error("Storage operation failed: {0}")]
+    Storage(#[from] StorageError),
 }
 
-pub async fn parse_chat_command(context: &Context) -> Result<ActionResult, BotCommandError> {
+pub async fn parse_chat_command(context: &Context<'_>) -> Result<ActionResult, BotCommandError> {
     let text = context.message.text().trim();
+    let config = context.bot.config();
+    let prefixes = config.prefixes(context.chat.id());
+
+    let command_text = strip_prefix(text, prefixes)
+        .ok_or_else(|| BotCommandError::MissingPrefix(prefixes.to_vec()))?;
+    let prefix = &text[..text.len() - command_text.len()];
+
+    let stages = split_pipeline(command_text)?;
+    let (last, rest) = stages.split_last().ok_or(BotCommandError::ParseFailed)?;
+    let mut context = context.clone();
+    let mut quit = false;
+
+    for stage in rest {
+        let (name, result) = run_stage(&context, prefix, stage).await?;
+        quit |= result.quit;
+        context.input = Some(result.text.ok_or(BotCommandError::NoPipeOutput(name))?);
+    }
+
+    let (_, result) = run_stage(&context, prefix, last).await?;
+
+    Ok(ActionResult {
+        quit: quit || result.quit,
+        ..result
+    })
+}
+
+async fn run_stage(
+    context: &Context<'_>,
+    prefix: &str,
+    stage: &[String],
+) -> Result<(String, ActionResult), BotCommandError> {
+    if stage.is_empty() {
+        return Err(BotCommandError::ParseFailed);
+    }
+
+    let mut split = context
+        .bot
+        .aliases()
+        .expand(context.bot.commands(), stage.to_vec())?;
+    let name = split.first().cloned().unwrap_or_default();
+    // add the prefix as a dummy command name to the start of the vec
+    split.insert(0, prefix.to_string());
+
+    let result = context.bot.commands().dispatch(context, split).await?;
+    Ok((name, result))
+}
+
+/// Splits a command line into words, grouped into pipeline stages at every
+/// word that is a lone `|`.
+fn split_pipeline(text: &str) -> Result<Vec<Vec<String>>, BotCommandError> {
+    let words = shell_words::split(text).map_err(|_| BotCommandError::ParseFailed)?;
+
+    Ok(words
+        .split(|word| word == "|")
+        .map(|stage| stage.to_vec())
+        .collect())
+}
 
-    if !text.starts_with('!') {
-        return Err(BotCommandError::MissingPrefix);
-    }
-
-    let command_text = text.trim_start_matches('!');
-    let mut split = shell_words::split(command_text).map_err(|_| BotCommandError::ParseFailed)?;
-    // add a dummy command name to the start of the vec
-    split.insert(0, "!".to_string());
-    let command = BotCommand::try_parse_from(split)?;
-
-    match command.action {
-        BotAction::Quit => Ok(ActionResult::quit(true)),
-        BotAction::Ping => Ok(ActionResult::reply("Pong!".into())),
-        BotAction::MsgId => {
-            let id = context
-                .message
-                .reply_to_message_id()
-                .unwrap_or(context.message.id());
-            Ok(ActionResult::edit(InputMessage::markdown(format!(
-                "Message ID: `{}`",
-                id
-            ))))
+/// Escapes text so that markdown shows it as written.
+pub(crate) fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
         }
-        BotAction::ChatId => Ok(ActionResult::edit(InputMessage::markdown(format!(
-            "Chat ID: `{}`",
-            context.chat.id()
-        )))),
-        BotAction::Case(args) => {
-            let reply = context
-                .message
-                .get_reply()
-                .await?
-                .map(|m| m.text().to_string());
-
-            args.handle(reply.as_deref())
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Strips the longest of `prefixes` that `text` starts with.
+pub fn strip_prefix<'a>(text: &'a str, prefixes: &[String]) -> Option<&'a str> {
+    prefixes
+        .iter()
+        .filter_map(|p| text.strip_prefix(p.as_str()).map(|rest| (p.len(), rest)))
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, rest)| rest)
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry containing all the commands that ship with shabby.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(HelpCommand)
+            .register(QuitCommand)
+            .register(PingCommand)
+            .register(MsgIdCommand)
+            .register(ChatIdCommand)
+            .register(CaseCommand)
+            .register(TextCommand)
+            .register(DiceCommand)
+            .register(DiceStatsCommand)
+            .register(RollCommand)
+            .register(AliasCommand)
+            .register(NoteCommand)
+            .register(RemindCommand)
+            .register(ScheduleCommand);
+        registry
+    }
+
+    /// Registers a command, replacing any existing command with the same name.
+    pub fn register<C>(&mut self, command: C) -> &mut Self
+    where
+        C: Command + 'static,
+    {
+        if let Some(index) = self
+            .commands
+            .iter()
+            .position(|c| c.name() == command.name())
+        {
+            debug!(name = command.name(), "Replacing registered command");
+            self.commands[index] = Box::new(command);
+        } else {
+            self.commands.push(Box::new(command));
         }
-        BotAction::Dice(args) => args.handle(),
+
+        self
+    }
+
+    /// Finds a command by its name or one of its aliases.
+    pub fn get(&self, name: &str) -> Option<&dyn Command> {
+        self.commands
+            .iter()
+            .find(|c| c.name() == name || c.aliases().contains(&name))
+            .map(|c| c.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Command> {
+        self.commands.iter().map(|c| c.as_ref())
+    }
+
+    /// Builds the root clap command with every registered command as a subcommand.
+    pub fn clap_command(&self) -> clap::Command {
+        self.iter().fold(
+            clap::Command::new(env!("CARGO_PKG_NAME"))
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .disable_help_subcommand(true),
+            |root, command| {
+                root.subcommand(
+                    command.args(clap::Command::new(command.name()).aliases(command.aliases())),
+                )
+            },
+        )
+    }
+
+    /// Parses `args` (including the leading program name) and runs the matching command.
+    pub async fn dispatch<I, T>(
+        &self,
+        context: &Context<'_>,
+        args: I,
+    ) -> Result<ActionResult, BotCommandError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let matches = self.clap_command().try_get_matches_from(args)?;
+        let (name, sub_matches) = matches.subcommand().ok_or(BotCommandError::ParseFailed)?;
+        let command = self
+            .get(name)
+            .ok_or_else(|| BotCommandError::UnknownCommand(name.to_string()))?;
+
+        command.handle(context, sub_matches).await
     }
 }
 
@@ -110,6 +280,7 @@ impl ActionResult {
             } else {
                 None
             },
+            text: None,
         }
     }
 
@@ -117,6 +288,7 @@ impl ActionResult {
         Self {
             quit: false,
             response: Some(ActionResponse::Edit(new_message)),
+            text: None,
         }
     }
 
@@ -124,6 +296,80 @@ impl ActionResult {
         Self {
             quit: false,
             response: Some(ActionResponse::Reply(response)),
+            text: None,
         }
     }
+
+    /// Sets the plain text output passed on to the next command in a pipeline.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builtins_clap_command() {
+        CommandRegistry::with_builtins()
+            .clap_command()
+            .debug_assert();
+    }
+
+    #[test]
+    fn test_get_by_alias() {
+        let registry = CommandRegistry::with_builtins();
+        assert_eq!(registry.get("c").map(|c| c.name()), Some("case"));
+        assert_eq!(registry.get("case").map(|c| c.name()), Some("case"));
+        assert!(registry.get("nope").is_none());
+    }
+
+    #[test]
+    fn test_subcommand_help_uses_bin_name() {
+        let mut root = CommandRegistry::with_builtins()
+            .clap_command()
+            .bin_name("!");
+        root.build();
+        let help = root.find_subcommand_mut("c").unwrap().render_long_help();
+        assert!(help.to_string().contains("Usage: ! case"));
+    }
+
+    #[test]
+    fn test_split_pipeline_ignores_quoted_pipes() {
+        assert_eq!(
+            split_pipeline(r#"case u "a | b" | case r 'c |d'"#).unwrap(),
+            [vec!["case", "u", "a | b"], vec!["case", "r", "c |d"]]
+        );
+        assert_eq!(split_pipeline("ping").unwrap(), [vec!["ping"]]);
+    }
+
+    #[test]
+    fn test_split_pipeline_keeps_embedded_pipes() {
+        assert_eq!(
+            split_pipeline("text bold a|b | note save x a|b").unwrap(),
+            [
+                vec!["text", "bold", "a|b"],
+                vec!["note", "save", "x", "a|b"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        let text = "snake_case *stars* `ticks` [link](url) 1. #tag";
+        let (parsed, entities) =
+            grammers_client::parsers::parse_markdown_message(&escape_markdown(text));
+        assert_eq!(parsed, text);
+        assert!(entities.is_empty());
+    }
+
+    #[test]
+    fn test_strip_prefix_prefers_longest() {
+        let prefixes = ["/".to_string(), "//".to_string(), "!".to_string()];
+        assert_eq!(strip_prefix("//ping", &prefixes), Some("ping"));
+        assert_eq!(strip_prefix("!ping", &prefixes), Some("ping"));
+        assert_eq!(strip_prefix(".ping", &prefixes), None);
+    }
 }
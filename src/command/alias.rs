@@ -0,0 +1,265 @@
+use std::{collections::BTreeMap, sync::RwLock};
+
+use async_trait::async_trait;
+use clap::{ArgMatches, Args, FromArgMatches, Subcommand};
+use grammers_client::InputMessage;
+
+use crate::Context;
+
+use super::{ActionResult, BotCommandError, Command, CommandRegistry};
+
+/// How many times an alias may expand into another alias before giving up.
+const MAX_ALIAS_DEPTH: usize = 8;
+
+/// User-defined command aliases, shared between the config and the `alias` command.
+#[derive(Debug, Default)]
+pub struct AliasStore {
+    aliases: RwLock<BTreeMap<String, String>>,
+}
+
+pub struct AliasCommand;
+
+#[derive(Args, Debug)]
+pub struct AliasArgs {
+    #[command(subcommand)]
+    pub action: AliasAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AliasAction {
+    /// Adds or replaces an alias.
+    Add {
+        /// The name to invoke the alias by.
+        name: String,
+
+        /// The command line the alias expands to, may contain `$1`..`$N` and `$@`.
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        expansion: Vec<String>,
+    },
+
+    /// Removes an alias.
+    #[command(alias = "rm")]
+    Remove { name: String },
+
+    /// Lists all aliases.
+    #[command(alias = "ls")]
+    List,
+}
+
+impl AliasStore {
+    pub fn new(aliases: BTreeMap<String, String>) -> Self {
+        Self {
+            aliases: RwLock::new(aliases),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.aliases.read().unwrap().get(name).cloned()
+    }
+
+    pub fn insert(&self, name: String, expansion: String) -> Option<String> {
+        self.aliases.write().unwrap().insert(name, expansion)
+    }
+
+    pub fn remove(&self, name: &str) -> Option<String> {
+        self.aliases.write().unwrap().remove(name)
+    }
+
+    /// Replaces all aliases at once, such as after the config is reloaded.
+    pub fn replace(&self, aliases: BTreeMap<String, String>) {
+        *self.aliases.write().unwrap() = aliases;
+    }
+
+    pub fn list(&self) -> Vec<(String, String)> {
+        self.aliases
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Expands `args` while its first element names an alias rather than a command.
+    pub fn expand(
+        &self,
+        commands: &CommandRegistry,
+        mut args: Vec<String>,
+    ) -> Result<Vec<String>, BotCommandError> {
+        for _ in 0..MAX_ALIAS_DEPTH {
+            let Some(name) = args.first() else {
+                return Ok(args);
+            };
+
+            if commands.get(name).is_some() {
+                return Ok(args);
+            }
+
+            let Some(template) = self.get(name) else {
+                return Ok(args);
+            };
+
+            args = expand(&template, &args[1..]).map_err(|_| BotCommandError::ParseFailed)?;
+        }
+
+        Err(BotCommandError::AliasRecursion(
+            args.first().cloned().unwrap_or_default(),
+        ))
+    }
+}
+
+/// Expands a single alias template with the given arguments.
+///
+/// A `$@` token is replaced by all arguments and `$N` anywhere in a token by the
+/// N-th argument. If the template has no placeholders, the arguments are appended.
+pub fn expand(template: &str, args: &[String]) -> Result<Vec<String>, shell_words::ParseError> {
+    let mut used_placeholder = false;
+    let mut expanded = Vec::new();
+
+    for token in shell_words::split(template)? {
+        if token == "$@" {
+            used_placeholder = true;
+            expanded.extend(args.iter().cloned());
+        } else {
+            let (token, substituted) = substitute_positional(&token, args);
+            used_placeholder |= substituted;
+            expanded.push(token);
+        }
+    }
+
+    if !used_placeholder {
+        expanded.extend(args.iter().cloned());
+    }
+
+    Ok(expanded)
+}
+
+fn substitute_positional(token: &str, args: &[String]) -> (String, bool) {
+    let mut result = String::with_capacity(token.len());
+    let mut substituted = false;
+    let mut rest = token;
+
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let digits = after.bytes().take_while(u8::is_ascii_digit).count();
+
+        match after[..digits].parse::<usize>() {
+            Ok(n) if n > 0 => {
+                substituted = true;
+                if let Some(arg) = args.get(n - 1) {
+                    result.push_str(arg);
+                }
+            }
+            _ => result.push_str(&rest[index..index + 1 + digits]),
+        }
+
+        rest = &after[digits..];
+    }
+
+    result.push_str(rest);
+    (result, substituted)
+}
+
+#[async_trait]
+impl Command for AliasCommand {
+    fn name(&self) -> &'static str {
+        "alias"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        AliasArgs::augment_args(command).about("Manages command aliases")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let args = AliasArgs::from_arg_matches(matches)?;
+        let aliases = context.bot.aliases();
+
+        let text = match args.action {
+            AliasAction::Add { name, expansion } => {
+                if context.bot.commands().get(&name).is_some() {
+                    format!("`{}` is already a command", name)
+                } else {
+                    let expansion = shell_words::join(expansion);
+                    context.bot.storage().set_alias(&name, &expansion).await?;
+                    aliases.insert(name.clone(), expansion.clone());
+                    format!("Added alias `{}` → `{}`", name, expansion)
+                }
+            }
+            AliasAction::Remove { name } => {
+                context.bot.storage().remove_alias(&name).await?;
+                match aliases.remove(&name) {
+                    Some(_) => format!("Removed alias `{}`", name),
+                    None => format!("No alias named `{}`", name),
+                }
+            }
+            AliasAction::List => {
+                let list = aliases.list();
+                if list.is_empty() {
+                    "No aliases defined".to_string()
+                } else {
+                    list.iter()
+                        .map(|(name, expansion)| format!("`{}` → `{}`", name, expansion))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+        };
+
+        Ok(ActionResult::edit(InputMessage::markdown(text)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_expand_appends_without_placeholders() {
+        let expanded = expand("case r", &args(&["hello", "world"])).unwrap();
+        assert_eq!(expanded, ["case", "r", "hello", "world"]);
+    }
+
+    #[test]
+    fn test_expand_all_arguments() {
+        let expanded = expand("case u $@ !", &args(&["a b", "c"])).unwrap();
+        assert_eq!(expanded, ["case", "u", "a b", "c", "!"]);
+    }
+
+    #[test]
+    fn test_expand_positional() {
+        let expanded = expand("case $2 'x$1y' $3", &args(&["one", "u"])).unwrap();
+        assert_eq!(expanded, ["case", "u", "xoney", ""]);
+    }
+
+    #[test]
+    fn test_expand_keeps_literal_dollars() {
+        let expanded = expand("echo $ $0 $x", &[]).unwrap();
+        assert_eq!(expanded, ["echo", "$", "$0", "$x"]);
+    }
+
+    #[test]
+    fn test_store_expands_recursively_and_detects_loops() {
+        let commands = CommandRegistry::with_builtins();
+        let store = AliasStore::default();
+        store.insert("cr".into(), "case r".into());
+        store.insert("x".into(), "cr $@".into());
+        assert_eq!(
+            store.expand(&commands, args(&["x", "hi"])).unwrap(),
+            ["case", "r", "hi"]
+        );
+
+        store.insert("loop".into(), "loop".into());
+        assert!(matches!(
+            store.expand(&commands, args(&["loop"])),
+            Err(BotCommandError::AliasRecursion(_))
+        ));
+    }
+}
@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use clap::ArgMatches;
+use grammers_client::InputMessage;
+
+use crate::Context;
+
+use super::{ActionResult, BotCommandError, Command};
+
+pub struct QuitCommand;
+
+pub struct PingCommand;
+
+pub struct MsgIdCommand;
+
+pub struct ChatIdCommand;
+
+#[async_trait]
+impl Command for QuitCommand {
+    fn name(&self) -> &'static str {
+        "quit"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        command.about("Disconnects and shuts down the bot")
+    }
+
+    async fn handle(
+        &self,
+        _context: &Context<'_>,
+        _matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        Ok(ActionResult::quit(true))
+    }
+}
+
+#[async_trait]
+impl Command for PingCommand {
+    fn name(&self) -> &'static str {
+        "ping"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        command.about("Replies with a pong")
+    }
+
+    async fn handle(
+        &self,
+        _context: &Context<'_>,
+        _matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        Ok(ActionResult::reply("Pong!".into()).with_text("Pong!"))
+    }
+}
+
+#[async_trait]
+impl Command for MsgIdCommand {
+    fn name(&self) -> &'static str {
+        "msg-id"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        command.about("Shows the ID of the replied-to message or the command message")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        _matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let id = context
+            .message
+            .reply_to_message_id()
+            .unwrap_or(context.message.id());
+        Ok(
+            ActionResult::edit(InputMessage::markdown(format!("Message ID: `{}`", id)))
+                .with_text(id.to_string()),
+        )
+    }
+}
+
+#[async_trait]
+impl Command for ChatIdCommand {
+    fn name(&self) -> &'static str {
+        "chat-id"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        command.about("Shows the ID of the current chat")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        _matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        Ok(ActionResult::edit(InputMessage::markdown(format!(
+            "Chat ID: `{}`",
+            context.chat.id()
+        )))
+        .with_text(context.chat.id().to_string()))
+    }
+}
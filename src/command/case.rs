@@ -1,8 +1,11 @@
 use std::str::FromStr;
 
-use clap::Args;
+use async_trait::async_trait;
+use clap::{ArgMatches, Args, FromArgMatches};
 
-use super::{ActionResult, BotCommandError};
+use crate::Context;
+
+use super::{ActionResult, BotCommandError, Command};
 
 #[derive(Clone, Copy, Debug)]
 pub enum CaseMode {
@@ -73,11 +76,15 @@ impl FromStr for CaseMode {
     }
 }
 
+pub struct CaseCommand;
+
 #[derive(Args, Debug)]
 pub struct CaseArgs {
+    /// The case mode: (u)pcase, (d)owncase, (i)nvert, (r)andomize or (a)lternate.
     #[arg()]
     pub mode: CaseMode,
 
+    /// The text to transform, used if not replying to a message.
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     pub text: Vec<String>,
 }
@@ -88,6 +95,32 @@ impl CaseArgs {
             .map(|s| s.to_string())
             .unwrap_or_else(|| self.text.join(" "));
         let transformed_text = self.mode.transform(&text);
-        Ok(ActionResult::edit(transformed_text.into()))
+        Ok(ActionResult::edit(transformed_text.clone().into()).with_text(transformed_text))
+    }
+}
+
+#[async_trait]
+impl Command for CaseCommand {
+    fn name(&self) -> &'static str {
+        "case"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["c"]
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        CaseArgs::augment_args(command).about("Changes the case of text")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let args = CaseArgs::from_arg_matches(matches)?;
+        let input = context.input_text().await?;
+
+        args.handle(input.as_deref())
     }
 }
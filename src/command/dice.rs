@@ -1,28 +1,345 @@
-use clap::Args;
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use clap::{ArgMatches, Args, FromArgMatches, error::ErrorKind};
 use grammers_client::{
-    InputMessage,
+    InputMessage, InvocationError,
     grammers_tl_types::types::MessageMediaDice,
     types::{Media, media::Dice},
 };
+use tracing::warn;
+
+use crate::{
+    Context,
+    dice::{DiceRule, OwnRoll, Reels, SLOT_MACHINE},
+    storage::DiceAttempts,
+};
+
+use super::{ActionResult, BotCommandError, Command};
+
+/// The widest bar drawn in a histogram.
+const HISTOGRAM_WIDTH: i64 = 20;
 
-use super::{ActionResult, BotCommandError};
+pub struct DiceCommand;
+
+pub struct DiceStatsCommand;
 
 #[derive(Args, Debug)]
 pub struct DiceArgs {
+    /// The dice emoji to send.
     pub emoji: String,
+
+    /// Keeps rolling until the outcome matches, such as `6`, `4..5`, or for 🎰
+    /// `jackpot`, `three-of-a-kind`, `any-bar` or reels like `bar,*,seven`.
+    #[arg(long, value_name = "RULE")]
+    pub until: Option<String>,
 }
 
 impl DiceArgs {
-    pub fn handle(&self) -> Result<ActionResult, BotCommandError> {
-        let dice_media = Media::Dice(Dice {
-            raw: MessageMediaDice {
-                emoticon: self.emoji.clone(),
-                value: 0,
-            },
-        });
-
-        Ok(ActionResult::reply(
-            InputMessage::text("").copy_media(&dice_media).silent(true),
-        ))
+    pub async fn handle(&self, context: &Context<'_>) -> Result<ActionResult, BotCommandError> {
+        let Some(until) = &self.until else {
+            return Ok(ActionResult::reply(dice_message(&self.emoji)));
+        };
+
+        let rule = DiceRule::parse(&self.emoji, until).map_err(|err| {
+            clap::Error::raw(
+                ErrorKind::InvalidValue,
+                format!("invalid value '{}' for '--until <RULE>': {}\n", until, err),
+            )
+        })?;
+
+        let Some(rolled) = roll_until(context, &self.emoji, &rule).await? else {
+            return Ok(ActionResult::edit(InputMessage::text(format!(
+                "Sent {} but it isn't a dice",
+                self.emoji
+            ))));
+        };
+
+        let text = match rolled.matched {
+            true => format!(
+                "{} {} after {} {}",
+                self.emoji,
+                describe(&rolled.dice),
+                rolled.attempts,
+                if rolled.attempts == 1 {
+                    "roll"
+                } else {
+                    "rolls"
+                }
+            ),
+            false => format!(
+                "{} gave up on {} after {} rolls",
+                self.emoji, until, rolled.attempts
+            ),
+        };
+
+        Ok(ActionResult::edit(InputMessage::text(text)))
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct DiceStatsArgs {
+    /// Only shows this dice emoji.
+    pub emoji: Option<String>,
+
+    /// Counts dice from every chat instead of only this one.
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Shows how often each value was rolled instead of reroll attempts.
+    #[arg(short = 'H', long)]
+    pub histogram: bool,
+}
+
+impl DiceStatsArgs {
+    pub async fn handle(&self, context: &Context<'_>) -> Result<ActionResult, BotCommandError> {
+        let storage = context.bot.storage();
+        let chat_id = (!self.all).then(|| context.chat.id());
+
+        let mut attempts = storage.dice_attempts(chat_id).await?;
+        if let Some(emoji) = &self.emoji {
+            attempts.retain(|a| &a.emoticon == emoji);
+        }
+
+        if attempts.is_empty() {
+            return Ok(ActionResult::edit(InputMessage::text("No dice rolled yet")));
+        }
+
+        let text = match self.histogram {
+            true => {
+                let mut histograms = Vec::new();
+                for stats in &attempts {
+                    let values = storage.dice_values(chat_id, &stats.emoticon).await?;
+                    histograms.push(render_histogram(&stats.emoticon, &values));
+                }
+                histograms.join("\n")
+            }
+            false => render_table(&attempts),
+        };
+
+        Ok(ActionResult::edit(InputMessage::markdown(format!(
+            "```\n{}\n```",
+            text.trim_end()
+        )))
+        .with_text(text))
+    }
+}
+
+/// The outcome of rolling a dice until it matched a rule.
+struct Rolled {
+    /// The last dice rolled, which is left in the chat.
+    dice: Dice,
+    attempts: usize,
+    /// Whether the last dice matched, rather than the attempts running out.
+    matched: bool,
+}
+
+/// Rolls `emoji` in reply to the command until it matches `rule` or the
+/// configured attempts run out, deleting every miss along the way and
+/// recording statistics for the chat. Returns `None` if a sent message turns
+/// out not to be a dice.
+async fn roll_until(
+    context: &Context<'_>,
+    emoji: &str,
+    rule: &DiceRule,
+) -> Result<Option<Rolled>, InvocationError> {
+    let limits = context.bot.config().reroll();
+    let client = context.bot.client();
+    let limiter = context.bot.limiter();
+    let chat_id = context.chat.id();
+    let mut values = Vec::new();
+
+    loop {
+        if !values.is_empty() {
+            tokio::time::sleep(limits.delay).await;
+        }
+        let message = dice_message(emoji).reply_to(Some(context.message.id()));
+        let message = limiter
+            .call(chat_id, client.send_message(&context.chat, message))
+            .await?;
+        context
+            .bot
+            .own_dice()
+            .insert(chat_id, message.id(), OwnRoll::Command);
+
+        let Some(Media::Dice(dice)) = message.media() else {
+            return Ok(None);
+        };
+
+        values.push(dice.raw.value);
+        let matched = rule.accepts(dice.raw.value);
+
+        if matched || values.len() >= limits.max_attempts as usize {
+            if let Err(err) = context
+                .bot
+                .storage()
+                .record_dice_rolls(chat_id, emoji, &values, matched)
+                .await
+            {
+                warn!(?err, "Failed to record dice statistics");
+            }
+
+            return Ok(Some(Rolled {
+                dice,
+                attempts: values.len(),
+                matched,
+            }));
+        }
+
+        limiter.call(chat_id, message.delete()).await?;
+    }
+}
+
+fn dice_message(emoji: &str) -> InputMessage {
+    let dice_media = Media::Dice(Dice {
+        raw: MessageMediaDice {
+            emoticon: emoji.to_string(),
+            value: 0,
+        },
+    });
+
+    InputMessage::text("").copy_media(&dice_media).silent(true)
+}
+
+/// Describes the outcome of a dice, showing the reels of a slot machine.
+fn describe(dice: &Dice) -> String {
+    match Reels::decode(dice.raw.value) {
+        Some(reels) if dice.raw.emoticon == SLOT_MACHINE => reels.to_string(),
+        _ => dice.raw.value.to_string(),
+    }
+}
+
+/// Renders reroll attempts as a table with a row per emoticon.
+fn render_table(attempts: &[DiceAttempts]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "  {:>6} {:>8} {:>5} {:>4} {:>7}",
+        "rolls", "attempts", "avg", "most", "gave up"
+    );
+
+    for stats in attempts {
+        let average = stats.attempts as f64 / stats.rolls.max(1) as f64;
+        let _ = writeln!(
+            out,
+            "{} {:>6} {:>8} {:>5.1} {:>4} {:>7}",
+            stats.emoticon,
+            stats.rolls,
+            stats.attempts,
+            average,
+            stats.most_attempts,
+            stats.gave_up
+        );
+    }
+
+    out
+}
+
+/// Renders how often each value of an emoticon was rolled as a bar chart,
+/// showing the reels of a slot machine instead of its values.
+fn render_histogram(emoticon: &str, values: &[(i32, i64)]) -> String {
+    let total = values.iter().map(|(_, count)| count).sum::<i64>().max(1);
+    let most = values.iter().map(|(_, count)| *count).max().unwrap_or(1);
+    let labels = values
+        .iter()
+        .map(|(value, _)| match Reels::decode(*value) {
+            Some(reels) if emoticon == SLOT_MACHINE => reels.to_string(),
+            _ => value.to_string(),
+        })
+        .collect::<Vec<_>>();
+    let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", emoticon);
+
+    for (label, (_, count)) in labels.iter().zip(values) {
+        let bar = (count * HISTOGRAM_WIDTH + most - 1) / most;
+        let _ = writeln!(
+            out,
+            "{:>label_width$} {:<width$} {} ({:.0}%)",
+            label,
+            "█".repeat(bar as usize),
+            count,
+            *count as f64 * 100.0 / total as f64,
+            width = HISTOGRAM_WIDTH as usize,
+        );
+    }
+
+    out
+}
+
+#[async_trait]
+impl Command for DiceCommand {
+    fn name(&self) -> &'static str {
+        "dice"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        DiceArgs::augment_args(command).about("Sends an animated dice")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        DiceArgs::from_arg_matches(matches)?.handle(context).await
+    }
+}
+
+#[async_trait]
+impl Command for DiceStatsCommand {
+    fn name(&self) -> &'static str {
+        "dicestats"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        DiceStatsArgs::augment_args(command).about("Shows how often dice were rerolled")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        DiceStatsArgs::from_arg_matches(matches)?
+            .handle(context)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let attempts = [DiceAttempts {
+            emoticon: "🎲".to_string(),
+            rolls: 3,
+            attempts: 10,
+            most_attempts: 6,
+            gave_up: 1,
+        }];
+
+        assert_eq!(
+            render_table(&attempts),
+            indoc::indoc! {"
+                   rolls attempts   avg most gave up
+                🎲      3       10   3.3    6       1
+            "}
+        );
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        assert_eq!(
+            render_histogram("🎲", &[(1, 1), (6, 3)]),
+            indoc::indoc! {"
+                🎲
+                1 ███████              1 (25%)
+                6 ████████████████████ 3 (75%)
+            "}
+        );
     }
 }
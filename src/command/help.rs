@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use clap::{ArgMatches, Args, FromArgMatches};
+use grammers_client::InputMessage;
+
+use crate::Context;
+
+use super::{ActionResult, BotCommandError, Command};
+
+pub struct HelpCommand;
+
+#[derive(Args, Debug)]
+pub struct HelpArgs {
+    /// The command to show help for.
+    pub command: Option<String>,
+}
+
+#[async_trait]
+impl Command for HelpCommand {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        HelpArgs::augment_args(command).about("Shows help for all commands or a single command")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let args = HelpArgs::from_arg_matches(matches)?;
+        let config = context.bot.config();
+        let prefix = config
+            .prefixes(context.chat.id())
+            .first()
+            .map(String::as_str)
+            .unwrap_or_default();
+
+        let mut root = context.bot.commands().clap_command().bin_name(prefix);
+        root.build();
+
+        let help = match args.command {
+            Some(name) => match root.find_subcommand_mut(&name) {
+                Some(subcommand) => subcommand.render_long_help(),
+                None => {
+                    return Ok(ActionResult::edit(InputMessage::markdown(format!(
+                        "Unknown command: `{}`",
+                        name
+                    ))));
+                }
+            },
+            None => root.render_long_help(),
+        };
+
+        Ok(ActionResult::edit(help_message(&help.to_string())))
+    }
+}
+
+/// Formats clap help output as a Telegram code block.
+pub fn help_message(help: &str) -> InputMessage {
+    InputMessage::markdown(format!("```\n{}\n```", help.trim_end()))
+}
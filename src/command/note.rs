@@ -0,0 +1,411 @@
+use std::sync::LazyLock;
+
+use async_trait::async_trait;
+use clap::{ArgMatches, Args, FromArgMatches, Subcommand};
+use grammers_client::{
+    InputMessage,
+    grammers_tl_types::enums::MessageEntity,
+    types::{Media, PackedChat},
+};
+use regex::Regex;
+use tracing::warn;
+
+use crate::{
+    Context,
+    storage::{MediaSource, Note},
+};
+
+use super::{ActionResponse, ActionResult, BotCommandError, Command, escape_markdown};
+
+/// Matches `#name` references to notes that are not part of a longer word.
+static NOTE_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\B#(\w+)").unwrap());
+
+pub struct NoteCommand;
+
+#[derive(Args, Debug)]
+pub struct NoteArgs {
+    #[command(subcommand)]
+    pub action: NoteAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NoteAction {
+    /// Saves the replied-to message, or the given text, as a note.
+    Save {
+        #[arg(value_parser = parse_note_name)]
+        name: String,
+
+        /// The text to save, used if not replying to a message.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        text: Vec<String>,
+    },
+
+    /// Replaces the command message with a note.
+    Get { name: String },
+
+    /// Lists all notes.
+    #[command(alias = "ls")]
+    List,
+
+    /// Removes a note.
+    #[command(alias = "rm")]
+    Remove { name: String },
+}
+
+fn parse_note_name(name: &str) -> Result<String, String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Ok(name.to_string())
+    } else {
+        Err("Note names may only contain letters, digits and underscores".to_string())
+    }
+}
+
+#[async_trait]
+impl Command for NoteCommand {
+    fn name(&self) -> &'static str {
+        "note"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["n"]
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        NoteArgs::augment_args(command).about("Saves and recalls notes, also usable as #name")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let args = NoteArgs::from_arg_matches(matches)?;
+        let storage = context.bot.storage();
+
+        let text = match args.action {
+            NoteAction::Save { name, text } => {
+                let note = match context.message.get_reply().await? {
+                    Some(reply) => Note {
+                        name: name.clone(),
+                        text: reply.text().to_string(),
+                        markdown: reply.markdown_text(),
+                        media: reply.media().map(|_| MediaSource {
+                            chat: reply.chat().pack().to_bytes().to_vec(),
+                            message_id: reply.id(),
+                        }),
+                    },
+                    None => {
+                        let text = context.input.clone().unwrap_or_else(|| text.join(" "));
+                        if text.is_empty() {
+                            return Ok(ActionResult::edit(
+                                "Reply to a message or give some text to save".into(),
+                            ));
+                        }
+
+                        Note {
+                            name: name.clone(),
+                            markdown: escape_markdown(&text),
+                            text,
+                            media: None,
+                        }
+                    }
+                };
+
+                storage.save_note(note).await?;
+                format!("Saved note `{}`", name)
+            }
+            NoteAction::Get { name } => match storage.note(&name).await? {
+                Some(note) => {
+                    let text = note.text.clone();
+                    let response = note_response(context, &note).await?;
+                    return Ok(ActionResult {
+                        quit: false,
+                        response: Some(response),
+                        text: Some(text),
+                    });
+                }
+                None => format!("No note named `{}`", name),
+            },
+            NoteAction::List => {
+                let names = storage.note_names().await?;
+                if names.is_empty() {
+                    "No notes saved".to_string()
+                } else {
+                    names
+                        .iter()
+                        .map(|name| format!("`#{}`", name))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+            NoteAction::Remove { name } => match storage.remove_note(&name).await? {
+                true => format!("Removed note `{}`", name),
+                false => format!("No note named `{}`", name),
+            },
+        };
+
+        Ok(ActionResult::edit(InputMessage::markdown(text)))
+    }
+}
+
+/// Expands `#name` references to notes in the context's message.
+///
+/// A message consisting of just a reference is replaced by the whole note,
+/// including formatting and media. Otherwise each reference is replaced inline
+/// with the note's plain text, keeping the message's own formatting. Returns
+/// `None` if no saved notes are referenced.
+pub async fn expand_note_shorthand(
+    context: &Context<'_>,
+) -> Result<Option<ActionResponse>, BotCommandError> {
+    let message = &context.message;
+    let text = message.text();
+    let names: Vec<String> = NOTE_TAG
+        .captures_iter(text)
+        .map(|c| c[1].to_string())
+        .collect();
+
+    if names.is_empty() {
+        return Ok(None);
+    }
+
+    let notes = context.bot.storage().notes_named(names).await?;
+
+    if let [note] = notes.as_slice()
+        && text.trim().strip_prefix('#') == Some(note.name.as_str())
+    {
+        return Ok(Some(note_response(context, note).await?));
+    }
+
+    if notes.is_empty() {
+        return Ok(None);
+    }
+
+    let entities = message.fmt_entities().cloned().unwrap_or_default();
+    let (expanded, entities) = expand_tags(text, entities, &notes);
+
+    Ok(Some(ActionResponse::Edit(
+        InputMessage::text(expanded).fmt_entities(entities),
+    )))
+}
+
+/// Replaces each tag naming one of `notes` with its text, moving `entities`
+/// so that they keep covering the same parts of the message.
+fn expand_tags(
+    text: &str,
+    mut entities: Vec<MessageEntity>,
+    notes: &[Note],
+) -> (String, Vec<MessageEntity>) {
+    let mut expanded = String::new();
+    // The UTF-16 range of each replaced tag and the length of its replacement,
+    // since that is what entity offsets count in
+    let mut replacements = Vec::new();
+    let mut last = 0;
+
+    for c in NOTE_TAG.captures_iter(text) {
+        let Some(note) = notes.iter().find(|n| n.name == c[1]) else {
+            continue;
+        };
+        let tag = c.get(0).unwrap();
+
+        let start = utf16_len(&text[..tag.start()]);
+        replacements.push((
+            start,
+            start + utf16_len(tag.as_str()),
+            utf16_len(&note.text),
+        ));
+        expanded.push_str(&text[last..tag.start()]);
+        expanded.push_str(&note.text);
+        last = tag.end();
+    }
+    expanded.push_str(&text[last..]);
+
+    for entity in &mut entities {
+        let (offset, length) = span_mut(entity);
+        let start = remap(*offset, &replacements, false);
+        let end = remap(*offset + *length, &replacements, true);
+        (*offset, *length) = (start, end - start);
+    }
+
+    (expanded, entities)
+}
+
+/// Moves a UTF-16 position in the original text to the expanded text. A
+/// position inside a replaced tag moves to the start of the replacement, or
+/// its end if it is the end of an entity.
+fn remap(position: i32, replacements: &[(i32, i32, i32)], is_end: bool) -> i32 {
+    let mut shift = 0;
+
+    for &(start, end, len) in replacements {
+        if position >= end {
+            shift += len - (end - start);
+        } else if position > start {
+            return start + shift + if is_end { len } else { 0 };
+        } else {
+            break;
+        }
+    }
+
+    position + shift
+}
+
+fn utf16_len(s: &str) -> i32 {
+    s.encode_utf16().count() as i32
+}
+
+/// Gets the offset and length of an entity of any kind.
+fn span_mut(entity: &mut MessageEntity) -> (&mut i32, &mut i32) {
+    macro_rules! span {
+        ($($variant:ident),*) => {
+            match entity {
+                $(MessageEntity::$variant(e) => (&mut e.offset, &mut e.length),)*
+            }
+        };
+    }
+
+    span!(
+        Unknown,
+        Mention,
+        Hashtag,
+        BotCommand,
+        Url,
+        Email,
+        Bold,
+        Italic,
+        Code,
+        Pre,
+        TextUrl,
+        MentionName,
+        InputMessageEntityMentionName,
+        Phone,
+        Cashtag,
+        Underline,
+        Strike,
+        BankCard,
+        Spoiler,
+        CustomEmoji,
+        Blockquote
+    )
+}
+
+async fn note_response(
+    context: &Context<'_>,
+    note: &Note,
+) -> Result<ActionResponse, BotCommandError> {
+    let message = InputMessage::markdown(&note.markdown);
+
+    let media = match &note.media {
+        Some(source) => fetch_media(context, source).await?,
+        None => None,
+    };
+
+    // Media can't be added to a text message by editing, so it has to be resent
+    Ok(match media {
+        Some(media) => ActionResponse::Replace(message.copy_media(&media)),
+        None => ActionResponse::Edit(message),
+    })
+}
+
+async fn fetch_media(
+    context: &Context<'_>,
+    source: &MediaSource,
+) -> Result<Option<Media>, BotCommandError> {
+    let Ok(chat) = PackedChat::from_bytes(&source.chat) else {
+        warn!("Stored note has an invalid media chat");
+        return Ok(None);
+    };
+
+    let media = context
+        .bot
+        .client()
+        .get_messages_by_id(chat, &[source.message_id])
+        .await?
+        .into_iter()
+        .flatten()
+        .next()
+        .and_then(|m| m.media());
+
+    if media.is_none() {
+        warn!(
+            message_id = source.message_id,
+            "Media for note is no longer available"
+        );
+    }
+
+    Ok(media)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_note_tag_requires_word_start() {
+        let names: Vec<_> = NOTE_TAG
+            .captures_iter("#one see #two and a#three (#four)")
+            .map(|c| c[1].to_string())
+            .collect();
+        assert_eq!(names, ["one", "two", "four"]);
+    }
+
+    #[test]
+    fn test_expand_tags_keeps_formatting() {
+        use grammers_client::grammers_tl_types::types::{MessageEntityBold, MessageEntityItalic};
+
+        let note = Note {
+            name: "addr".to_string(),
+            text: "1 Main St".to_string(),
+            markdown: String::new(),
+            media: None,
+        };
+        let entities = vec![
+            MessageEntityBold {
+                offset: 0,
+                length: 2,
+            }
+            .into(),
+            MessageEntityItalic {
+                offset: 3,
+                length: 9,
+            }
+            .into(),
+            MessageEntityBold {
+                offset: 13,
+                length: 5,
+            }
+            .into(),
+            MessageEntityBold {
+                offset: 4,
+                length: 2,
+            }
+            .into(),
+        ];
+
+        let (text, entities) = expand_tags("hi #addr and #nope here", entities, &[note]);
+        assert_eq!(text, "hi 1 Main St and #nope here");
+        assert_eq!(
+            entities,
+            [
+                MessageEntityBold {
+                    offset: 0,
+                    length: 2,
+                }
+                .into(),
+                MessageEntityItalic {
+                    offset: 3,
+                    length: 13,
+                }
+                .into(),
+                MessageEntityBold {
+                    offset: 17,
+                    length: 5,
+                }
+                .into(),
+                MessageEntityBold {
+                    offset: 3,
+                    length: 9,
+                }
+                .into(),
+            ] as [MessageEntity; 4]
+        );
+    }
+}
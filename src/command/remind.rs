@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone};
+use clap::{ArgMatches, Args, FromArgMatches, Subcommand};
+use grammers_client::InputMessage;
+
+use crate::{
+    Context,
+    scheduler::unix_now,
+    storage::{Job, JobKind},
+};
+
+use super::{ActionResult, BotCommandError, Command, escape_markdown};
+
+pub struct RemindCommand;
+
+pub struct ScheduleCommand;
+
+#[derive(Args, Debug)]
+pub struct RemindArgs {
+    #[command(subcommand)]
+    pub action: Option<RemindAction>,
+
+    /// How long to wait, e.g. `90s`, `2h30m` or `1w2d`.
+    #[arg(required = true, value_parser = parse_duration)]
+    pub delay: Option<Duration>,
+
+    /// Send the reminder to Saved Messages instead of the current chat.
+    #[arg(short, long)]
+    pub saved: bool,
+
+    /// What to be reminded of, used if not replying to a message.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub text: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RemindAction {
+    /// Lists pending reminders and scheduled messages for this chat.
+    #[command(alias = "ls")]
+    List,
+
+    /// Cancels a pending reminder or scheduled message.
+    #[command(alias = "rm")]
+    Cancel { id: i64 },
+}
+
+#[derive(Args, Debug)]
+pub struct ScheduleArgs {
+    /// When to send the message, as `HH:MM` (the next such time) or `YYYY-MM-DDTHH:MM`.
+    #[arg(value_parser = parse_time)]
+    pub time: NaiveDateTime,
+
+    /// Send the message to Saved Messages instead of the current chat.
+    #[arg(short, long)]
+    pub saved: bool,
+
+    /// The message to send, used if not replying to a message.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub text: Vec<String>,
+}
+
+#[async_trait]
+impl Command for RemindCommand {
+    fn name(&self) -> &'static str {
+        "remind"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        RemindArgs::augment_args(command)
+            .args_conflicts_with_subcommands(true)
+            .subcommand_negates_reqs(true)
+            .about("Sends a reminder after a delay")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let args = RemindArgs::from_arg_matches(matches)?;
+        let scheduler = context.bot.scheduler();
+
+        let text = match args.action {
+            Some(RemindAction::List) => {
+                let jobs = scheduler.pending(context.chat.id()).await?;
+                if jobs.is_empty() {
+                    "Nothing pending".to_string()
+                } else {
+                    jobs.iter()
+                        .map(|job| {
+                            format!(
+                                "`{}` {} {}: {}",
+                                job.id,
+                                format_time(job.due_at),
+                                match job.kind {
+                                    JobKind::Reminder => "⏰",
+                                    JobKind::Scheduled => "🗓",
+                                },
+                                escape_markdown(&job.text)
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+            Some(RemindAction::Cancel { id }) => {
+                match scheduler.cancel(context.chat.id(), id).await? {
+                    true => format!("Cancelled `{}`", id),
+                    false => format!("Nothing pending with ID `{}`", id),
+                }
+            }
+            None => {
+                let delay = args.delay.ok_or(BotCommandError::ParseFailed)?;
+                let due_at = unix_now().saturating_add(delay.as_secs() as i64);
+                let Some(text) = job_text(context, args.text).await? else {
+                    return Ok(missing_text());
+                };
+
+                let job = new_job(context, JobKind::Reminder, args.saved, due_at, text);
+                let id = scheduler.add(job.clone()).await?;
+                format!(
+                    "⏰ Reminding at {} (`{}`): {}",
+                    format_time(due_at),
+                    id,
+                    escape_markdown(&job.text)
+                )
+            }
+        };
+
+        Ok(ActionResult::edit(InputMessage::markdown(text)))
+    }
+}
+
+#[async_trait]
+impl Command for ScheduleCommand {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        ScheduleArgs::augment_args(command).about("Sends a message at a given time")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let args = ScheduleArgs::from_arg_matches(matches)?;
+        let Some(due_at) = Local
+            .from_local_datetime(&args.time)
+            .earliest()
+            .map(|t| t.timestamp())
+        else {
+            return Ok(ActionResult::edit(
+                "That time does not exist locally".into(),
+            ));
+        };
+
+        let Some(text) = job_text(context, args.text).await? else {
+            return Ok(missing_text());
+        };
+
+        let job = new_job(context, JobKind::Scheduled, args.saved, due_at, text);
+        let id = context.bot.scheduler().add(job).await?;
+
+        Ok(ActionResult::edit(InputMessage::markdown(format!(
+            "🗓 Message scheduled for {} (`{}`)",
+            format_time(due_at),
+            id
+        ))))
+    }
+}
+
+async fn job_text(
+    context: &Context<'_>,
+    text: Vec<String>,
+) -> Result<Option<String>, BotCommandError> {
+    let text = match text.is_empty() {
+        true => context.input_text().await?,
+        false => Some(text.join(" ")),
+    };
+
+    Ok(text.filter(|t| !t.trim().is_empty()))
+}
+
+fn new_job(context: &Context<'_>, kind: JobKind, saved: bool, due_at: i64, text: String) -> Job {
+    let (chat, reply_to) = match saved {
+        true => (context.bot.me().pack(), None),
+        false => (context.chat.pack(), Some(context.message.id())),
+    };
+
+    Job {
+        id: 0,
+        kind,
+        chat: chat.to_bytes().to_vec(),
+        reply_to,
+        text,
+        due_at,
+    }
+}
+
+fn missing_text() -> ActionResult {
+    ActionResult::edit("Reply to a message or give some text to send".into())
+}
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Parses durations like `90s`, `2h30m` or `1w2d`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let mut total: u64 = 0;
+    let mut number = String::new();
+
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(format!("Unknown duration unit: {}", c)),
+        };
+
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("Missing number before unit {}", c))?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|v| total.checked_add(v))
+            .ok_or("Duration is too long")?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err("Missing unit after number (one of s, m, h, d, w)".to_string());
+    }
+
+    if total == 0 {
+        return Err("Duration must be greater than zero".to_string());
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+/// Parses a local date and time, or a time of day which is taken to mean its next occurrence.
+pub fn parse_time(s: &str) -> Result<NaiveDateTime, String> {
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M") {
+        return Ok(datetime);
+    }
+
+    let time = NaiveTime::parse_from_str(s, "%H:%M")
+        .map_err(|_| format!("Invalid time, expected HH:MM or YYYY-MM-DDTHH:MM: {}", s))?;
+    let now = Local::now().naive_local();
+    let today = now.date().and_time(time);
+
+    Ok(match today > now {
+        true => today,
+        false => today + chrono::Duration::days(1),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h30m"), Ok(Duration::from_secs(9000)));
+        assert_eq!(
+            parse_duration("1w2d"),
+            Ok(Duration::from_secs(9 * 24 * 60 * 60))
+        );
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("0m").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = Local::now().naive_local();
+        let next = parse_time("18:00").unwrap();
+        assert!(next > now && next - now <= chrono::Duration::days(1));
+        assert_eq!(
+            parse_time("2030-01-02T03:04").unwrap().to_string(),
+            "2030-01-02 03:04:00"
+        );
+        assert!(parse_time("25:00").is_err());
+    }
+
+    #[test]
+    fn test_remind_parses_subcommands_and_delays() {
+        let command = RemindCommand.args(clap::Command::new("remind"));
+        command.clone().debug_assert();
+
+        let matches = command
+            .clone()
+            .try_get_matches_from(["remind", "list"])
+            .unwrap();
+        let args = RemindArgs::from_arg_matches(&matches).unwrap();
+        assert!(matches!(args.action, Some(RemindAction::List)));
+
+        let matches = command
+            .try_get_matches_from(["remind", "2h", "check", "the", "deploy"])
+            .unwrap();
+        let args = RemindArgs::from_arg_matches(&matches).unwrap();
+        assert!(args.action.is_none());
+        assert_eq!(args.delay, Some(Duration::from_secs(7200)));
+        assert_eq!(args.text, ["check", "the", "deploy"]);
+    }
+}
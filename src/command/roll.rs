@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use clap::{ArgMatches, Args, FromArgMatches, error::ErrorKind};
+use grammers_client::InputMessage;
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::{
+    Context,
+    roll::{Expression, split_expressions},
+};
+
+use super::{ActionResult, BotCommandError, Command};
+
+pub struct RollCommand;
+
+#[derive(Args, Debug)]
+pub struct RollArgs {
+    /// Seeds the random number generator so that rolls can be reproduced.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Dice notation like `4d6kh3+2`, `2d20kl1`, `3d10!` or `4dF`, with
+    /// several expressions separated by commas or spaces. Expressions starting
+    /// with a minus go after `--`.
+    #[arg(required = true)]
+    pub expressions: Vec<String>,
+}
+
+impl RollArgs {
+    pub fn handle(&self) -> Result<ActionResult, BotCommandError> {
+        let expressions = split_expressions(&self.expressions.join(" "))
+            .iter()
+            .map(|s| {
+                Expression::parse(s).map_err(|err| {
+                    clap::Error::raw(
+                        ErrorKind::InvalidValue,
+                        format!("invalid dice notation '{}': {}\n", s.trim(), err),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+
+        let mut lines = Vec::new();
+        let mut totals = Vec::new();
+        for expression in &expressions {
+            let outcome = expression.roll(&mut rng);
+            lines.push(format!("`{}` {}", expression, outcome.breakdown()));
+            totals.push(outcome.total.to_string());
+        }
+
+        Ok(ActionResult::edit(InputMessage::markdown(lines.join("\n")))
+            .with_text(totals.join("\n")))
+    }
+}
+
+#[async_trait]
+impl Command for RollCommand {
+    fn name(&self) -> &'static str {
+        "roll"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["r"]
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        RollArgs::augment_args(command).about("Rolls dice written in dice notation")
+    }
+
+    async fn handle(
+        &self,
+        _context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        RollArgs::from_arg_matches(matches)?.handle()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<RollArgs, clap::Error> {
+        let matches =
+            RollArgs::augment_args(clap::Command::new("roll")).try_get_matches_from(args)?;
+        RollArgs::from_arg_matches(&matches)
+    }
+
+    #[test]
+    fn test_seed_after_expressions() {
+        let args = parse(&["roll", "4d6", "-", "2", "--seed", "4"]).unwrap();
+        assert_eq!(args.seed, Some(4));
+        assert_eq!(args.expressions, ["4d6", "-", "2"]);
+
+        let args = parse(&["roll", "--seed", "4", "--", "-2+d4"]).unwrap();
+        assert_eq!(args.seed, Some(4));
+        assert_eq!(args.expressions, ["-2+d4"]);
+    }
+
+    #[test]
+    fn test_separate_expressions() {
+        let totals = |args: &[&str]| {
+            let text = parse(args).unwrap().handle().unwrap().text.unwrap();
+            text.lines()
+                .map(|total| total.parse::<i64>().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let d20s = totals(&["roll", "d20", "d20"]);
+        assert_eq!(d20s.len(), 2);
+        assert!(d20s.iter().all(|total| (1..=20).contains(total)));
+
+        let rolls = totals(&["roll", "4d6", "2"]);
+        assert_eq!(rolls.len(), 2);
+        assert!((4..=24).contains(&rolls[0]));
+        assert_eq!(rolls[1], 2);
+    }
+}
@@ -0,0 +1,386 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use clap::{ArgMatches, Args, FromArgMatches, error::ErrorKind};
+
+use crate::Context;
+
+use super::{ActionResult, BotCommandError, Command};
+
+/// How many combining marks zalgo adds to each character unless told otherwise.
+const DEFAULT_ZALGO_INTENSITY: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextMode {
+    Bold,
+    Script,
+    Fraktur,
+    Monospace,
+    DoubleStruck,
+    SmallCaps,
+    Fullwidth,
+    UpsideDown,
+    Leet,
+    Zalgo { intensity: u8 },
+    Reverse,
+    Spaced,
+}
+
+/// A mathematical alphanumeric font, with the characters that Unicode encodes
+/// elsewhere because they existed before the rest of the font did.
+struct Font {
+    upper: u32,
+    lower: u32,
+    digits: Option<u32>,
+    exceptions: &'static [(char, char)],
+}
+
+const BOLD: Font = Font {
+    upper: 0x1D400,
+    lower: 0x1D41A,
+    digits: Some(0x1D7CE),
+    exceptions: &[],
+};
+
+const SCRIPT: Font = Font {
+    upper: 0x1D49C,
+    lower: 0x1D4B6,
+    digits: None,
+    exceptions: &[
+        ('B', 'ℬ'),
+        ('E', 'ℰ'),
+        ('F', 'ℱ'),
+        ('H', 'ℋ'),
+        ('I', 'ℐ'),
+        ('L', 'ℒ'),
+        ('M', 'ℳ'),
+        ('R', 'ℛ'),
+        ('e', 'ℯ'),
+        ('g', 'ℊ'),
+        ('o', 'ℴ'),
+    ],
+};
+
+const FRAKTUR: Font = Font {
+    upper: 0x1D504,
+    lower: 0x1D51E,
+    digits: None,
+    exceptions: &[('C', 'ℭ'), ('H', 'ℌ'), ('I', 'ℑ'), ('R', 'ℜ'), ('Z', 'ℨ')],
+};
+
+const MONOSPACE: Font = Font {
+    upper: 0x1D670,
+    lower: 0x1D68A,
+    digits: Some(0x1D7F6),
+    exceptions: &[],
+};
+
+const DOUBLE_STRUCK: Font = Font {
+    upper: 0x1D538,
+    lower: 0x1D552,
+    digits: Some(0x1D7D8),
+    exceptions: &[
+        ('C', 'ℂ'),
+        ('H', 'ℍ'),
+        ('N', 'ℕ'),
+        ('P', 'ℙ'),
+        ('Q', 'ℚ'),
+        ('R', 'ℝ'),
+        ('Z', 'ℤ'),
+    ],
+};
+
+const SMALL_CAPS: &str = "ᴀʙᴄᴅᴇꜰɢʜɪᴊᴋʟᴍɴᴏᴘǫʀꜱᴛᴜᴠᴡxʏᴢ";
+
+/// Characters that look like others turned upside down, in both directions
+/// where that works.
+const UPSIDE_DOWN: &[(char, char)] = &[
+    ('a', 'ɐ'),
+    ('b', 'q'),
+    ('c', 'ɔ'),
+    ('d', 'p'),
+    ('e', 'ǝ'),
+    ('f', 'ɟ'),
+    ('g', 'ƃ'),
+    ('h', 'ɥ'),
+    ('i', 'ᴉ'),
+    ('j', 'ɾ'),
+    ('k', 'ʞ'),
+    ('m', 'ɯ'),
+    ('n', 'u'),
+    ('r', 'ɹ'),
+    ('t', 'ʇ'),
+    ('v', 'ʌ'),
+    ('w', 'ʍ'),
+    ('y', 'ʎ'),
+    ('A', '∀'),
+    ('C', 'Ɔ'),
+    ('E', 'Ǝ'),
+    ('F', 'Ⅎ'),
+    ('G', '⅁'),
+    ('J', 'ſ'),
+    ('L', '˥'),
+    ('M', 'W'),
+    ('P', 'Ԁ'),
+    ('T', '⊥'),
+    ('U', '∩'),
+    ('V', 'Λ'),
+    ('Y', '⅄'),
+    ('1', 'Ɩ'),
+    ('2', 'ᄅ'),
+    ('3', 'Ɛ'),
+    ('4', 'ㄣ'),
+    ('5', 'ϛ'),
+    ('6', '9'),
+    ('7', 'ㄥ'),
+    ('.', '˙'),
+    (',', '\''),
+    ('?', '¿'),
+    ('!', '¡'),
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+    ('<', '>'),
+    ('_', '‾'),
+    ('&', '⅋'),
+];
+
+const LEET: &[(char, char)] = &[
+    ('a', '4'),
+    ('b', '8'),
+    ('e', '3'),
+    ('g', '9'),
+    ('i', '1'),
+    ('o', '0'),
+    ('s', '5'),
+    ('t', '7'),
+];
+
+impl TextMode {
+    pub fn transform(&self, text: &str) -> String {
+        match self {
+            TextMode::Bold => BOLD.apply(text),
+            TextMode::Script => SCRIPT.apply(text),
+            TextMode::Fraktur => FRAKTUR.apply(text),
+            TextMode::Monospace => MONOSPACE.apply(text),
+            TextMode::DoubleStruck => DOUBLE_STRUCK.apply(text),
+            TextMode::SmallCaps => text
+                .chars()
+                .map(|c| match c {
+                    'a'..='z' => SMALL_CAPS.chars().nth((c as u8 - b'a') as usize).unwrap(),
+                    _ => c,
+                })
+                .collect(),
+            TextMode::Fullwidth => text
+                .chars()
+                .map(|c| match c {
+                    ' ' => '\u{3000}',
+                    '!'..='~' => char::from_u32(c as u32 + 0xFEE0).unwrap_or(c),
+                    _ => c,
+                })
+                .collect(),
+            TextMode::UpsideDown => text
+                .chars()
+                .rev()
+                .map(|c| {
+                    UPSIDE_DOWN
+                        .iter()
+                        .find_map(|&(a, b)| match c {
+                            _ if c == a => Some(b),
+                            _ if c == b => Some(a),
+                            _ => None,
+                        })
+                        .unwrap_or(c)
+                })
+                .collect(),
+            TextMode::Leet => text
+                .chars()
+                .map(|c| {
+                    LEET.iter()
+                        .find(|(letter, _)| *letter == c.to_ascii_lowercase())
+                        .map_or(c, |&(_, digit)| digit)
+                })
+                .collect(),
+            TextMode::Zalgo { intensity } => {
+                use rand::Rng;
+                let mut rng = rand::rng();
+                let mut out = String::new();
+                for c in text.chars() {
+                    out.push(c);
+                    if c.is_whitespace() {
+                        continue;
+                    }
+                    for _ in 0..*intensity {
+                        // Combining diacritical marks
+                        out.extend(char::from_u32(rng.random_range(0x300..=0x36F)));
+                    }
+                }
+                out
+            }
+            TextMode::Reverse => text.chars().rev().collect(),
+            TextMode::Spaced => text
+                .chars()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+impl Font {
+    fn apply(&self, text: &str) -> String {
+        text.chars()
+            .map(|c| {
+                if let Some(&(_, exception)) = self.exceptions.iter().find(|(from, _)| *from == c) {
+                    return exception;
+                }
+
+                let code = match c {
+                    'A'..='Z' => self.upper + (c as u32 - 'A' as u32),
+                    'a'..='z' => self.lower + (c as u32 - 'a' as u32),
+                    '0'..='9' => match self.digits {
+                        Some(digits) => digits + (c as u32 - '0' as u32),
+                        None => return c,
+                    },
+                    _ => return c,
+                };
+                char::from_u32(code).unwrap_or(c)
+            })
+            .collect()
+    }
+}
+
+impl FromStr for TextMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bold" => Ok(TextMode::Bold),
+            "script" => Ok(TextMode::Script),
+            "fraktur" | "gothic" => Ok(TextMode::Fraktur),
+            "monospace" | "mono" => Ok(TextMode::Monospace),
+            "double-struck" | "bb" => Ok(TextMode::DoubleStruck),
+            "small-caps" | "sc" => Ok(TextMode::SmallCaps),
+            "fullwidth" | "wide" => Ok(TextMode::Fullwidth),
+            "upside-down" | "flip" => Ok(TextMode::UpsideDown),
+            "leet" | "1337" => Ok(TextMode::Leet),
+            "zalgo" => Ok(TextMode::Zalgo {
+                intensity: DEFAULT_ZALGO_INTENSITY,
+            }),
+            "reverse" | "rev" => Ok(TextMode::Reverse),
+            "spaced" => Ok(TextMode::Spaced),
+            _ => Err(format!("Unknown text mode: {}", s)),
+        }
+    }
+}
+
+pub struct TextCommand;
+
+#[derive(Args, Debug)]
+pub struct TextArgs {
+    /// The transform: bold, script, fraktur, monospace, double-struck,
+    /// small-caps, fullwidth, upside-down, leet, zalgo, reverse or spaced.
+    #[arg()]
+    pub mode: TextMode,
+
+    /// How many combining marks zalgo piles onto each character, only
+    /// accepted with that mode.
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(1..=20))]
+    pub intensity: Option<u8>,
+
+    /// The text to transform, used if not replying to a message.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub text: Vec<String>,
+}
+
+impl TextArgs {
+    pub fn handle(&self, text: Option<&str>) -> Result<ActionResult, BotCommandError> {
+        let text = text
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| self.text.join(" "));
+        let mode = match (self.mode, self.intensity) {
+            (TextMode::Zalgo { .. }, Some(intensity)) => TextMode::Zalgo { intensity },
+            (_, Some(_)) => {
+                return Err(clap::Error::raw(
+                    ErrorKind::ArgumentConflict,
+                    "--intensity can only be used with the zalgo mode\n",
+                )
+                .into());
+            }
+            (mode, None) => mode,
+        };
+        let transformed_text = mode.transform(&text);
+        Ok(ActionResult::edit(transformed_text.clone().into()).with_text(transformed_text))
+    }
+}
+
+#[async_trait]
+impl Command for TextCommand {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["t"]
+    }
+
+    fn args(&self, command: clap::Command) -> clap::Command {
+        TextArgs::augment_args(command).about("Restyles text with unicode fonts and effects")
+    }
+
+    async fn handle(
+        &self,
+        context: &Context<'_>,
+        matches: &ArgMatches,
+    ) -> Result<ActionResult, BotCommandError> {
+        let args = TextArgs::from_arg_matches(matches)?;
+        let input = context.input_text().await?;
+
+        args.handle(input.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fonts() {
+        assert_eq!(TextMode::Bold.transform("Ab 1!"), "𝐀𝐛 𝟏!");
+        assert_eq!(TextMode::Script.transform("Hello"), "ℋℯ𝓁𝓁ℴ");
+        assert_eq!(TextMode::Fraktur.transform("CHaR"), "ℭℌ𝔞ℜ");
+        assert_eq!(TextMode::Monospace.transform("az09"), "𝚊𝚣𝟶𝟿");
+        assert_eq!(TextMode::DoubleStruck.transform("RZx2"), "ℝℤ𝕩𝟚");
+    }
+
+    #[test]
+    fn test_effects() {
+        assert_eq!(TextMode::SmallCaps.transform("Small caps"), "Sᴍᴀʟʟ ᴄᴀᴘꜱ");
+        assert_eq!(TextMode::Fullwidth.transform("Hi 5!"), "Ｈｉ　５！");
+        assert_eq!(TextMode::UpsideDown.transform("hello!"), "¡ollǝɥ");
+        assert_eq!(TextMode::Leet.transform("Leet Speak"), "L337 5p34k");
+        assert_eq!(TextMode::Reverse.transform("abc"), "cba");
+        assert_eq!(TextMode::Spaced.transform("abc"), "a b c");
+    }
+
+    #[test]
+    fn test_zalgo_intensity() {
+        let zalgo = TextMode::Zalgo { intensity: 4 }.transform("ab c");
+        assert_eq!(zalgo.chars().count(), 4 + 3 * 4);
+        assert!(zalgo.starts_with('a'));
+        assert!(zalgo.contains(' '));
+    }
+
+    #[test]
+    fn test_intensity_conflict() {
+        let args = TextArgs {
+            mode: TextMode::Bold,
+            intensity: Some(5),
+            text: vec!["text".to_string()],
+        };
+        assert!(matches!(
+            args.handle(None),
+            Err(BotCommandError::Clap(err)) if err.kind() == ErrorKind::ArgumentConflict
+        ));
+    }
+}
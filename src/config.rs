@@ -1,28 +1,149 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
-use color_eyre::{Result, eyre::OptionExt};
-use kdl::{KdlDocument, KdlError};
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, bail, eyre},
+};
+use kdl::{KdlDocument, KdlEntry, KdlError, KdlNode};
 use thiserror::Error;
 use tracing::{debug, error, info};
 
-use crate::{cli::Cli, dirs, logging::LogLevel};
+use crate::{
+    cli::Cli,
+    dice::{self, DiceRule},
+    dirs,
+    logging::LogLevel,
+};
+
+pub use self::{check::check, dump::dump, watch::ConfigWatcher};
+
+mod check;
+mod dump;
+mod watch;
+
+const DEFAULT_PREFIX: &str = "!";
+
+/// The name of the profile defined by a `telegram` block without a name.
+const DEFAULT_PROFILE: &str = "default";
+
+/// How many updates are handled at the same time unless configured otherwise.
+const DEFAULT_UPDATE_CONCURRENCY: usize = 16;
+
+/// How long to wait for updates still being handled when shutting down.
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub struct Config {
+    path: Option<PathBuf>,
     log_level: Option<LogLevel>,
+    profiles: Vec<Profile>,
+    update_concurrency: usize,
+    drain_timeout: Duration,
+    prefixes: Vec<String>,
+    aliases: BTreeMap<String, String>,
+    dice: BTreeMap<String, DiceRule>,
+    reroll: RerollLimits,
+    chats: HashMap<i64, ChatConfig>,
+}
+
+/// How persistently dice are rerolled until they match their rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RerollLimits {
+    /// Rolls to make in total, counting the original one, before giving up.
+    pub max_attempts: u32,
+    /// How long to wait before each reroll.
+    pub delay: Duration,
+}
+
+/// A Telegram account to run as, each with its own session and storage.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    name: String,
     api_id: i32,
     api_hash: String,
-    phone_number: String,
+    phone_number: Option<String>,
+    password: Option<Secret>,
+    session_passphrase: Option<Secret>,
     session_filename: PathBuf,
+    storage_filename: PathBuf,
+    rate_limits: RateLimits,
 }
 
-#[derive(Debug, Default)]
+/// Budgets for outgoing requests, which Telegram limits per account.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    pub global: RateLimit,
+    pub per_chat: RateLimit,
+    /// Flood waits up to this long are slept through and retried automatically.
+    pub max_flood_wait: Duration,
+}
+
+/// Allows `requests` requests per `period`, in bursts of up to `requests`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+/// Where to get a secret such as a password from, if not prompting for it.
+#[derive(Debug, Clone)]
+enum Secret {
+    Value(String),
+    File(PathBuf),
+}
+
+/// Settings that can be overridden for a single chat.
+#[derive(Debug, Default, Clone)]
+pub struct ChatConfig {
+    pub prefixes: Option<Vec<String>>,
+    /// Dice rules that replace the global ones for the same emoticons.
+    pub dice: BTreeMap<String, DiceRule>,
+}
+
+#[derive(Debug, Default, Clone)]
 struct ConfigFile {
     pub log_level: Option<LogLevel>,
+    pub profiles: Vec<ProfileFile>,
+    pub storage_filename: Option<PathBuf>,
+    pub rate_limits: Option<RateLimits>,
+    pub update_concurrency: Option<usize>,
+    pub drain_timeout: Option<Duration>,
+    pub prefixes: Option<Vec<String>>,
+    pub aliases: BTreeMap<String, String>,
+    pub dice: BTreeMap<String, DiceRule>,
+    pub reroll: Option<RerollLimits>,
+    pub chats: HashMap<i64, ChatConfig>,
+    /// Keys set inside blocks whose other keys keep their defaults, such as
+    /// `rate_limit.per_chat`.
+    pub block_keys: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct ProfileFile {
+    pub name: String,
     pub api_id: Option<i32>,
     pub api_hash: Option<String>,
     pub phone_number: Option<String>,
+    pub password: Option<Secret>,
+    pub session_passphrase: Option<Secret>,
     pub session_filename: Option<PathBuf>,
+    pub storage_filename: Option<PathBuf>,
+}
+
+/// The config file merged with the command line and environment, before
+/// checking that each profile has everything it needs to connect.
+struct Merged {
+    config: Config,
+    rate_limits: RateLimits,
+    profiles: Vec<ProfileFile>,
+    /// The config file as it was parsed, if there is one.
+    file: Option<ConfigFile>,
 }
 
 #[derive(Debug, Error)]
@@ -33,85 +154,248 @@ enum ConfigFileError {
     #[error("Failed to parse config file")]
     Parse(#[from] KdlError),
 
-    #[error("Invalid value in config file")]
-    InvalidValue,
+    #[error("Invalid value for `{key}` in config file")]
+    InvalidValue { key: String, span: Range<usize> },
 }
 
 impl Config {
     pub fn from_cli(cli: &Cli) -> Result<Self> {
+        let Merged {
+            config,
+            rate_limits,
+            profiles,
+            ..
+        } = Self::merge(cli)?;
+
+        let profiles = profiles
+            .into_iter()
+            .map(|profile| Profile::from_file(profile, rate_limits))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { profiles, ..config })
+    }
+
+    /// Merges the config file with the command line and environment, without
+    /// checking that each profile has everything it needs to connect.
+    fn merge(cli: &Cli) -> Result<Merged> {
         let mut log_level: Option<LogLevel> = None;
         let mut config_file: Option<ConfigFile> = None;
-        let mut api_id: Option<i32> = None;
-        let mut api_hash: Option<String> = None;
-        let mut phone_number: Option<String> = None;
-        let mut session_filename: Option<PathBuf> = None;
+        let mut profiles: Vec<ProfileFile> = Vec::new();
+        let mut storage_filename: Option<PathBuf> = None;
+        let mut rate_limits: Option<RateLimits> = None;
+        let mut update_concurrency: Option<usize> = None;
+        let mut drain_timeout: Option<Duration> = None;
+        let mut prefixes: Option<Vec<String>> = None;
+        let mut aliases: BTreeMap<String, String> = BTreeMap::new();
+        let mut dice = dice::default_rules();
+        let mut reroll: Option<RerollLimits> = None;
+        let mut chats: HashMap<i64, ChatConfig> = HashMap::new();
 
-        let mut config_path: Option<PathBuf> = None;
+        let config_path = config_path(cli)?;
 
-        if let Some(cli_config_path) = &cli.config {
-            debug!("Config file specified in CLI");
-            config_path = Some(cli_config_path.to_path_buf());
-        } else {
-            debug!("No config file specified in CLI, trying default location");
-            let default_config_path = dirs::config()?.join("config.kdl");
-            if default_config_path.exists() {
-                debug!("Found default config");
-                config_path = Some(default_config_path);
-            }
+        if let Some(config_path) = &config_path {
+            config_file = Some(ConfigFile::load_file(config_path)?);
         }
 
-        if let Some(config_path) = config_path {
-            config_file = Some(ConfigFile::load_file(&config_path)?);
-        }
-
-        if let Some(config_file) = config_file {
+        if let Some(config_file) = config_file.clone() {
             log_level = config_file.log_level;
-            api_id = config_file.api_id;
-            api_hash = config_file.api_hash;
-            phone_number = config_file.phone_number;
-            session_filename = config_file.session_filename;
+            profiles = config_file.profiles;
+            storage_filename = config_file.storage_filename;
+            rate_limits = config_file.rate_limits;
+            update_concurrency = config_file.update_concurrency;
+            drain_timeout = config_file.drain_timeout;
+            prefixes = config_file.prefixes;
+            aliases = config_file.aliases;
+            dice.extend(config_file.dice);
+            reroll = config_file.reroll;
+            chats = config_file.chats;
         }
 
         if let Some(cli_log_level) = cli.log_level() {
             log_level = Some(cli_log_level);
         }
 
-        if let Some(cli_api_id) = cli.api_id {
-            api_id = Some(cli_api_id);
+        if profiles.is_empty() {
+            debug!("No profiles in config file, using the default profile");
+            profiles.push(ProfileFile {
+                name: DEFAULT_PROFILE.to_string(),
+                ..Default::default()
+            });
         }
 
-        if let Some(cli_api_hash) = &cli.api_hash {
-            api_hash = Some(cli_api_hash.to_string());
-        }
+        if !cli.profiles.is_empty() {
+            if let Some(unknown) = cli
+                .profiles
+                .iter()
+                .find(|name| !profiles.iter().any(|p| &p.name == *name))
+            {
+                bail!(
+                    "Unknown profile: {} (available: {})",
+                    unknown,
+                    profiles
+                        .iter()
+                        .map(|p| p.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
 
-        if let Some(cli_phone_number) = &cli.phone_number {
-            phone_number = Some(cli_phone_number.to_string());
+            profiles.retain(|p| cli.profiles.contains(&p.name));
         }
 
-        if let Some(cli_session) = &cli.session {
-            session_filename = Some(cli_session.to_path_buf());
+        let account_specific_cli = cli.phone_number.is_some()
+            || cli.password.is_some()
+            || cli.password_file.is_some()
+            || cli.session_passphrase.is_some()
+            || cli.session_passphrase_file.is_some()
+            || cli.session.is_some()
+            || cli.storage.is_some();
+
+        if profiles.len() > 1 && account_specific_cli {
+            bail!(
+                "Phone number, password, session and storage options can only be given for a single profile, select one with --profile"
+            );
         }
 
-        if session_filename.is_none() {
-            debug!("No session filename provided in config or CLI, using default state location");
-            let xdg_session = dirs::state()?.join("session");
+        let profiles = profiles
+            .into_iter()
+            .map(|profile| {
+                // The top-level storage filename predates profiles and belongs to the default one
+                let storage_filename = match profile.name == DEFAULT_PROFILE {
+                    true => profile
+                        .storage_filename
+                        .clone()
+                        .or(storage_filename.clone()),
+                    false => profile.storage_filename.clone(),
+                };
 
-            session_filename = Some(xdg_session);
-        }
+                ProfileFile {
+                    storage_filename,
+                    ..profile
+                }
+                .with_cli(cli)
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-        Ok(Self {
+        let config = Self {
+            path: config_path,
             log_level,
-            api_id: api_id.ok_or_eyre("API ID not provided")?,
-            api_hash: api_hash.ok_or_eyre("API hash not provided")?,
-            phone_number: phone_number.ok_or_eyre("Phone number not provided")?,
-            session_filename: session_filename.unwrap(),
+            profiles: Vec::new(),
+            update_concurrency: update_concurrency.unwrap_or(DEFAULT_UPDATE_CONCURRENCY),
+            drain_timeout: drain_timeout.unwrap_or(DEFAULT_DRAIN_TIMEOUT),
+            prefixes: prefixes.unwrap_or_else(|| vec![DEFAULT_PREFIX.to_string()]),
+            aliases,
+            dice,
+            reroll: reroll.unwrap_or_default(),
+            chats,
+        };
+
+        Ok(Merged {
+            config,
+            rate_limits: rate_limits.unwrap_or_default(),
+            profiles,
+            file: config_file,
         })
     }
 
+    /// Gets the path of the config file that was loaded, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
     pub fn log_level(&self) -> Option<LogLevel> {
         self.log_level
     }
 
+    /// Gets the profiles to run, in the order they were defined.
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    /// Gets how many updates may be handled at the same time across all chats.
+    pub fn update_concurrency(&self) -> usize {
+        self.update_concurrency
+    }
+
+    /// Gets how long updates still being handled may take to finish on shutdown.
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    /// Gets the command prefixes that apply in the given chat.
+    pub fn prefixes(&self, chat_id: i64) -> &[String] {
+        self.chats
+            .get(&chat_id)
+            .and_then(|c| c.prefixes.as_deref())
+            .unwrap_or(&self.prefixes)
+    }
+
+    pub fn reroll(&self) -> RerollLimits {
+        self.reroll
+    }
+
+    /// Gets the rule for a dice emoticon in the given chat, if there is one.
+    pub fn dice_rule(&self, chat_id: i64, emoticon: &str) -> Option<&DiceRule> {
+        self.chats
+            .get(&chat_id)
+            .and_then(|c| c.dice.get(emoticon))
+            .or_else(|| self.dice.get(emoticon))
+    }
+}
+
+/// Gets the config file to use, either the one given in the CLI or the one in
+/// the default location if it exists.
+fn config_path(cli: &Cli) -> Result<Option<PathBuf>> {
+    if let Some(cli_config_path) = &cli.config {
+        debug!("Config file specified in CLI");
+        return Ok(Some(cli_config_path.to_path_buf()));
+    }
+
+    debug!("No config file specified in CLI, trying default location");
+    let default_config_path = dirs::config()?.join("config.kdl");
+    if default_config_path.exists() {
+        debug!("Found default config");
+        return Ok(Some(default_config_path));
+    }
+
+    Ok(None)
+}
+
+impl Profile {
+    fn from_file(profile: ProfileFile, rate_limits: RateLimits) -> Result<Self> {
+        let ProfileFile {
+            name,
+            api_id,
+            api_hash,
+            phone_number,
+            password,
+            session_passphrase,
+            session_filename,
+            storage_filename,
+        } = profile;
+
+        Ok(Self {
+            api_id: api_id.ok_or_else(|| eyre!("API ID not provided for profile {}", name))?,
+            api_hash: api_hash
+                .ok_or_else(|| eyre!("API hash not provided for profile {}", name))?,
+            name,
+            phone_number,
+            password,
+            session_passphrase,
+            session_filename: session_filename.expect("session filename is set by with_cli"),
+            storage_filename: storage_filename.expect("storage filename is set by with_cli"),
+            rate_limits,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn api_id(&self) -> i32 {
         self.api_id
     }
@@ -120,13 +404,168 @@ impl Config {
         &self.api_hash
     }
 
-    pub fn phone_number(&self) -> &str {
-        &self.phone_number
+    /// Gets the phone number to log in with, which is not needed when logging in by QR code.
+    pub fn phone_number(&self) -> Option<&str> {
+        self.phone_number.as_deref()
+    }
+
+    /// Gets the two-step verification password, reading it from a file if configured so.
+    ///
+    /// Returns `None` if no password was configured, in which case it should be prompted for.
+    pub fn password(&self) -> Result<Option<String>> {
+        self.password.as_ref().map(Secret::read).transpose()
+    }
+
+    /// Gets the passphrase to encrypt the session file with, if one is configured.
+    pub fn session_passphrase(&self) -> Result<Option<String>> {
+        self.session_passphrase
+            .as_ref()
+            .map(Secret::read)
+            .transpose()
     }
 
     pub fn session_filename(&self) -> &PathBuf {
         &self.session_filename
     }
+
+    pub fn storage_filename(&self) -> &PathBuf {
+        &self.storage_filename
+    }
+
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+}
+
+impl ProfileFile {
+    /// Applies the command line and environment, and fills in default
+    /// locations for the session and storage files.
+    fn with_cli(self, cli: &Cli) -> Result<Self> {
+        let ProfileFile {
+            name,
+            mut api_id,
+            mut api_hash,
+            mut phone_number,
+            mut password,
+            mut session_passphrase,
+            mut session_filename,
+            mut storage_filename,
+        } = self;
+
+        if let Some(cli_api_id) = cli.api_id {
+            api_id = Some(cli_api_id);
+        }
+
+        if let Some(cli_api_hash) = &cli.api_hash {
+            api_hash = Some(cli_api_hash.to_string());
+        }
+
+        if let Some(cli_phone_number) = &cli.phone_number {
+            phone_number = Some(cli_phone_number.to_string());
+        }
+
+        if let Some(cli_password) = &cli.password {
+            password = Some(Secret::Value(cli_password.to_string()));
+        }
+
+        if let Some(cli_password_file) = &cli.password_file {
+            password = Some(Secret::File(cli_password_file.to_path_buf()));
+        }
+
+        if let Some(cli_passphrase) = &cli.session_passphrase {
+            session_passphrase = Some(Secret::Value(cli_passphrase.to_string()));
+        }
+
+        if let Some(cli_passphrase_file) = &cli.session_passphrase_file {
+            session_passphrase = Some(Secret::File(cli_passphrase_file.to_path_buf()));
+        }
+
+        if let Some(cli_session) = &cli.session {
+            session_filename = Some(cli_session.to_path_buf());
+        }
+
+        if let Some(cli_storage) = &cli.storage {
+            storage_filename = Some(cli_storage.to_path_buf());
+        }
+
+        let is_default = name == DEFAULT_PROFILE;
+
+        if session_filename.is_none() {
+            debug!(
+                profile = name,
+                "No session filename provided in config or CLI, using default state location"
+            );
+            let filename = match is_default {
+                true => "session".to_string(),
+                false => format!("session-{}", name),
+            };
+
+            session_filename = Some(dirs::state()?.join(filename));
+        }
+
+        if storage_filename.is_none() {
+            debug!(
+                profile = name,
+                "No storage filename provided in config or CLI, using default state location"
+            );
+            let filename = match is_default {
+                true => "shabby.db".to_string(),
+                false => format!("shabby-{}.db", name),
+            };
+
+            storage_filename = Some(dirs::state()?.join(filename));
+        }
+
+        Ok(Self {
+            name,
+            api_id,
+            api_hash,
+            phone_number,
+            password,
+            session_passphrase,
+            session_filename,
+            storage_filename,
+        })
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            global: RateLimit {
+                requests: 30,
+                period: Duration::from_secs(1),
+            },
+            per_chat: RateLimit {
+                requests: 20,
+                period: Duration::from_secs(60),
+            },
+            max_flood_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Default for RerollLimits {
+    fn default() -> Self {
+        Self {
+            max_attempts: 20,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl Secret {
+    fn read(&self) -> Result<String> {
+        match self {
+            Secret::Value(value) => Ok(value.clone()),
+            Secret::File(path) => {
+                let value = std::fs::read_to_string(path)
+                    .wrap_err_with(|| format!("Failed to read secret file: {}", path.display()))?;
+
+                Ok(value.trim_end_matches(['\r', '\n']).to_string())
+            }
+        }
+    }
 }
 
 impl ConfigFile {
@@ -140,80 +579,709 @@ impl ConfigFile {
 impl FromStr for ConfigFile {
     type Err = ConfigFileError;
 
+    /// Parses a config file, failing on the first invalid value.
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (config, errors) = ConfigFile::parse(s)?;
+
+        match errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(config),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Parses a config file, collecting every invalid value instead of stopping
+    /// at the first one. Only fails outright if the file isn't valid KDL.
+    fn parse(s: &str) -> Result<(Self, Vec<ConfigFileError>), KdlError> {
         let mut config = ConfigFile::default();
+        let mut errors = Vec::new();
         let doc: KdlDocument = s.parse()?;
 
-        if let Some(log_level) = doc.get_arg("log_level") {
-            if let Some(num_level) = log_level.as_integer() {
+        if let Some(node) = doc.get("log_level") {
+            let log_level = node.get(0);
+
+            if let Some(num_level) = log_level.and_then(|v| v.as_integer()) {
                 let level = num_level.into();
                 debug!(%level, "Parsed integer log level from config file");
                 config.log_level = Some(level);
-            } else if let Some(str_level) = log_level.as_string() {
+            } else if let Some(str_level) = log_level.and_then(|v| v.as_string()) {
                 if let Ok(level) = str_level.parse::<LogLevel>() {
                     debug!(%level, "Parsed string log level from config file");
                     config.log_level = Some(level);
                 } else {
                     error!("Log level key present in config but value is invalid");
-                    return Err(ConfigFileError::InvalidValue);
+                    errors.push(ConfigFileError::invalid("log_level", node));
                 }
             } else {
                 error!("Log level key present in config but value is missing or invalid");
-                return Err(ConfigFileError::InvalidValue);
-            }
-        }
-
-        if let Some(telegram) = doc.get("telegram") {
-            if let Some(children) = telegram.children() {
-                if let Some(api_id) = children.get_arg("api_id") {
-                    match api_id.as_integer() {
-                        Some(id) if id >= i32::MIN as i128 && id <= i32::MAX as i128 => {
-                            debug!("Parsed valid API ID from config file");
-                            config.api_id = Some(id as i32);
-                        }
-                        _ => {
-                            error!("API ID key present in config but value is missing or invalid");
-                            return Err(ConfigFileError::InvalidValue);
-                        }
+                errors.push(ConfigFileError::invalid("log_level", node));
+            }
+        }
+
+        for telegram in doc
+            .nodes()
+            .iter()
+            .filter(|n| n.name().value() == "telegram")
+        {
+            let name = match telegram.get(0) {
+                Some(name) => match name.as_string() {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => {
+                        error!("Telegram profile name present in config but is invalid");
+                        errors.push(ConfigFileError::invalid("telegram", telegram));
+                        continue;
                     }
+                },
+                None => DEFAULT_PROFILE.to_string(),
+            };
+
+            if config.profiles.iter().any(|p| p.name == name) {
+                error!(
+                    profile = name,
+                    "Telegram profile defined more than once in config"
+                );
+                errors.push(ConfigFileError::invalid("telegram", telegram));
+                continue;
+            }
+
+            let profile = parse_profile(name, telegram, &mut errors);
+            config.profiles.push(profile);
+        }
+
+        if let Some(node) = doc.get("storage_filename") {
+            if let Some(filename) = node.get(0).and_then(|v| v.as_string()) {
+                debug!("Parsed storage filename from config file");
+                config.storage_filename = Some(PathBuf::from(filename));
+            } else {
+                error!("Storage filename key present in config but value is missing or invalid");
+                errors.push(ConfigFileError::invalid("storage_filename", node));
+            }
+        }
+
+        if let Some(node) = doc.get("update_concurrency") {
+            match node
+                .get(0)
+                .and_then(|v| v.as_integer())
+                .and_then(|n| usize::try_from(n).ok())
+                .filter(|&n| n > 0)
+            {
+                Some(n) => config.update_concurrency = Some(n),
+                None => {
+                    error!("Update concurrency key present in config but value is invalid");
+                    errors.push(ConfigFileError::invalid("update_concurrency", node));
                 }
+            }
+        }
 
-                if let Some(api_hash) = children.get_arg("api_hash") {
-                    if let Some(hash) = api_hash.as_string() {
-                        debug!("Parsed API hash from config file");
-                        config.api_hash = Some(hash.to_string());
-                    } else {
-                        error!("API hash key present in config but value is missing or invalid");
-                        return Err(ConfigFileError::InvalidValue);
-                    }
+        if let Some(node) = doc.get("drain_timeout") {
+            match node
+                .get(0)
+                .and_then(|v| v.as_integer())
+                .and_then(|s| u64::try_from(s).ok())
+            {
+                Some(seconds) => config.drain_timeout = Some(Duration::from_secs(seconds)),
+                None => {
+                    error!("Drain timeout key present in config but value is invalid");
+                    errors.push(ConfigFileError::invalid("drain_timeout", node));
+                }
+            }
+        }
+
+        if let Some(rate_limit) = doc.get("rate_limit") {
+            config.rate_limits = Some(parse_rate_limits(rate_limit, &mut errors));
+            config.block_keys.extend(block_keys(rate_limit));
+        }
+
+        if let Some(prefixes) = doc.get("prefixes") {
+            match parse_prefixes("prefixes", prefixes) {
+                Ok(prefixes) => config.prefixes = Some(prefixes),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        if let Some(dice) = doc.get("dice") {
+            config.dice = parse_dice("dice", dice, &mut errors);
+        }
+
+        if let Some(reroll) = doc.get("reroll") {
+            config.reroll = Some(parse_reroll(reroll, &mut errors));
+            config.block_keys.extend(block_keys(reroll));
+        }
+
+        for alias in doc.nodes().iter().filter(|n| n.name().value() == "alias") {
+            let name = alias
+                .get(0)
+                .and_then(|v| v.as_string())
+                .filter(|s| !s.is_empty() && !s.contains(char::is_whitespace));
+            let expansion = alias
+                .get(1)
+                .and_then(|v| v.as_string())
+                .filter(|s| shell_words::split(s).is_ok_and(|w| !w.is_empty()));
+
+            match (name, expansion) {
+                (Some(name), Some(expansion)) => {
+                    debug!(name, expansion, "Parsed alias from config file");
+                    config
+                        .aliases
+                        .insert(name.to_string(), expansion.to_string());
+                }
+                _ => {
+                    error!("Alias key present in config but name or expansion is invalid");
+                    errors.push(ConfigFileError::invalid("alias", alias));
                 }
+            }
+        }
+
+        for chat in doc.nodes().iter().filter(|n| n.name().value() == "chat") {
+            let Some(id) = chat
+                .get(0)
+                .and_then(|v| v.as_integer())
+                .and_then(|id| i64::try_from(id).ok())
+            else {
+                error!("Chat key present in config but ID is missing or invalid");
+                errors.push(ConfigFileError::invalid("chat", chat));
+                continue;
+            };
 
-                if let Some(phone_number) = children.get_arg("phone_number") {
-                    if let Some(phone) = phone_number.as_string() {
-                        debug!("Parsed phone number from config file");
-                        config.phone_number = Some(phone.to_string());
-                    } else {
-                        error!(
-                            "Phone number key present in config but value is missing or invalid"
-                        );
-                        return Err(ConfigFileError::InvalidValue);
+            let mut chat_config = ChatConfig::default();
+
+            if let Some(children) = chat.children() {
+                if let Some(prefixes) = children.get("prefixes") {
+                    match parse_prefixes("chat.prefixes", prefixes) {
+                        Ok(prefixes) => chat_config.prefixes = Some(prefixes),
+                        Err(err) => errors.push(err),
                     }
                 }
 
-                if let Some(session_filename) = children.get_arg("session_filename") {
-                    if let Some(filename) = session_filename.as_string() {
-                        debug!("Parsed session filename from config file");
-                        config.session_filename = Some(PathBuf::from(filename));
-                    } else {
-                        error!(
-                            "Session filename key present in config but value is missing or invalid"
-                        );
-                        return Err(ConfigFileError::InvalidValue);
-                    }
+                if let Some(dice) = children.get("dice") {
+                    chat_config.dice = parse_dice("chat.dice", dice, &mut errors);
+                }
+            }
+
+            debug!(id, "Parsed chat settings from config file");
+            config.chats.insert(id, chat_config);
+        }
+
+        Ok((config, errors))
+    }
+}
+
+impl ConfigFileError {
+    fn invalid(key: &str, node: &KdlNode) -> Self {
+        let span = node.span();
+
+        Self::InvalidValue {
+            key: key.to_string(),
+            span: span.offset()..span.offset() + span.len(),
+        }
+    }
+}
+
+fn parse_profile(name: String, node: &KdlNode, errors: &mut Vec<ConfigFileError>) -> ProfileFile {
+    let mut profile = ProfileFile {
+        name,
+        ..Default::default()
+    };
+
+    let Some(children) = node.children() else {
+        return profile;
+    };
+
+    if let Some(node) = children.get("api_id") {
+        match node.get(0).and_then(|v| v.as_integer()) {
+            Some(id) if id >= i32::MIN as i128 && id <= i32::MAX as i128 => {
+                debug!("Parsed valid API ID from config file");
+                profile.api_id = Some(id as i32);
+            }
+            _ => {
+                error!("API ID key present in config but value is missing or invalid");
+                errors.push(ConfigFileError::invalid("telegram.api_id", node));
+            }
+        }
+    }
+
+    if let Some(node) = children.get("api_hash") {
+        if let Some(hash) = node.get(0).and_then(|v| v.as_string()) {
+            debug!("Parsed API hash from config file");
+            profile.api_hash = Some(hash.to_string());
+        } else {
+            error!("API hash key present in config but value is missing or invalid");
+            errors.push(ConfigFileError::invalid("telegram.api_hash", node));
+        }
+    }
+
+    if let Some(node) = children.get("phone_number") {
+        if let Some(phone) = node.get(0).and_then(|v| v.as_string()) {
+            debug!("Parsed phone number from config file");
+            profile.phone_number = Some(phone.to_string());
+        } else {
+            error!("Phone number key present in config but value is missing or invalid");
+            errors.push(ConfigFileError::invalid("telegram.phone_number", node));
+        }
+    }
+
+    profile.password = parse_secret(children, "password", errors);
+    profile.session_passphrase = parse_secret(children, "session_passphrase", errors);
+
+    if let Some(node) = children.get("session_filename") {
+        if let Some(filename) = node.get(0).and_then(|v| v.as_string()) {
+            debug!("Parsed session filename from config file");
+            profile.session_filename = Some(PathBuf::from(filename));
+        } else {
+            error!("Session filename key present in config but value is missing or invalid");
+            errors.push(ConfigFileError::invalid("telegram.session_filename", node));
+        }
+    }
+
+    if let Some(node) = children.get("storage_filename") {
+        if let Some(filename) = node.get(0).and_then(|v| v.as_string()) {
+            debug!("Parsed profile storage filename from config file");
+            profile.storage_filename = Some(PathBuf::from(filename));
+        } else {
+            error!("Storage filename key present in config but value is missing or invalid");
+            errors.push(ConfigFileError::invalid("telegram.storage_filename", node));
+        }
+    }
+
+    debug!(
+        profile = profile.name,
+        "Parsed Telegram profile from config file"
+    );
+    profile
+}
+
+/// Lists the keys set inside a block as `block.key`.
+fn block_keys(node: &KdlNode) -> impl Iterator<Item = String> + '_ {
+    node.children()
+        .into_iter()
+        .flat_map(|children| children.nodes())
+        .map(move |child| format!("{}.{}", node.name().value(), child.name().value()))
+}
+
+/// Parses a `rate_limit` block, with budgets given as `global 30 seconds=1`.
+fn parse_rate_limits(node: &KdlNode, errors: &mut Vec<ConfigFileError>) -> RateLimits {
+    let mut limits = RateLimits::default();
+
+    let Some(children) = node.children() else {
+        return limits;
+    };
+
+    for (key, limit) in [
+        ("global", &mut limits.global),
+        ("per_chat", &mut limits.per_chat),
+    ] {
+        if let Some(node) = children.get(key) {
+            match parse_rate_limit(node) {
+                Ok(parsed) => *limit = parsed,
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    if let Some(node) = children.get("max_flood_wait") {
+        match node
+            .get(0)
+            .and_then(|v| v.as_integer())
+            .and_then(|s| u32::try_from(s).ok())
+        {
+            Some(seconds) => limits.max_flood_wait = Duration::from_secs(seconds.into()),
+            None => {
+                error!("Max flood wait key present in config but value is missing or invalid");
+                errors.push(ConfigFileError::invalid("rate_limit.max_flood_wait", node));
+            }
+        }
+    }
+
+    debug!(?limits, "Parsed rate limits from config file");
+    limits
+}
+
+fn parse_rate_limit(node: &KdlNode) -> Result<RateLimit, ConfigFileError> {
+    let requests = node
+        .get(0)
+        .and_then(|v| v.as_integer())
+        .and_then(|r| u32::try_from(r).ok())
+        .filter(|&r| r > 0);
+    let seconds = node
+        .get("seconds")
+        .and_then(|v| v.as_integer())
+        .and_then(|s| u64::try_from(s).ok())
+        .filter(|&s| s > 0);
+
+    match (requests, seconds) {
+        (Some(requests), Some(seconds)) => Ok(RateLimit {
+            requests,
+            period: Duration::from_secs(seconds),
+        }),
+        _ => {
+            let key = format!("rate_limit.{}", node.name().value());
+            error!(
+                key,
+                "Rate limit present in config but request count or seconds are missing or invalid"
+            );
+            Err(ConfigFileError::invalid(&key, node))
+        }
+    }
+}
+
+/// Parses a secret given either directly as `key` or as a path in `key_file`.
+fn parse_secret(
+    children: &KdlDocument,
+    key: &str,
+    errors: &mut Vec<ConfigFileError>,
+) -> Option<Secret> {
+    let mut secret = None;
+
+    if let Some(node) = children.get(key) {
+        if let Some(value) = node.get(0).and_then(|v| v.as_string()) {
+            debug!(key, "Parsed secret from config file");
+            secret = Some(Secret::Value(value.to_string()));
+        } else {
+            error!(
+                key,
+                "Secret key present in config but value is missing or invalid"
+            );
+            errors.push(ConfigFileError::invalid(&format!("telegram.{}", key), node));
+        }
+    }
+
+    let file_key = format!("{}_file", key);
+    if let Some(node) = children.get(&file_key) {
+        if let Some(filename) = node.get(0).and_then(|v| v.as_string()) {
+            debug!(key = file_key, "Parsed secret file from config file");
+            secret = Some(Secret::File(PathBuf::from(filename)));
+        } else {
+            error!(
+                key = file_key,
+                "Secret file key present in config but value is missing or invalid"
+            );
+            errors.push(ConfigFileError::invalid(
+                &format!("telegram.{}", file_key),
+                node,
+            ));
+        }
+    }
+
+    secret
+}
+
+/// Parses a `dice` block, where each emoticon maps to the values to keep as in
+/// `"🎲" 5 6`, to a range as in `"🏀" min=4 max=5`, to a slot machine rule as
+/// in `"🎰" jackpot`, or to `off`.
+fn parse_dice(
+    key: &str,
+    node: &KdlNode,
+    errors: &mut Vec<ConfigFileError>,
+) -> BTreeMap<String, DiceRule> {
+    let mut rules = BTreeMap::new();
+
+    let Some(children) = node.children() else {
+        return rules;
+    };
+
+    for rule in children.nodes() {
+        let emoticon = rule.name().value();
+        let key = format!("{}.{}", key, emoticon);
+
+        match parse_dice_rule(emoticon, rule) {
+            Some(parsed) => {
+                debug!(emoticon, rule = %parsed, "Parsed dice rule from config file");
+                rules.insert(emoticon.to_string(), parsed);
+            }
+            None => {
+                error!(
+                    key,
+                    "Dice rule present in config but values are missing or invalid"
+                );
+                errors.push(ConfigFileError::invalid(&key, rule));
+            }
+        }
+    }
+
+    rules
+}
+
+fn parse_dice_rule(emoticon: &str, node: &KdlNode) -> Option<DiceRule> {
+    let entries = node.entries();
+    let value = |entry: &KdlEntry| {
+        entry
+            .value()
+            .as_integer()
+            .and_then(|v| i32::try_from(v).ok())
+    };
+
+    // Written as a string, such as `off` or the name of a slot machine rule
+    if let [entry] = entries
+        && entry.name().is_none()
+        && let Some(rule) = entry.value().as_string()
+    {
+        return DiceRule::parse(emoticon, rule).ok();
+    }
+
+    if entries.iter().any(|e| e.name().is_some()) {
+        let mut min = None;
+        let mut max = None;
+
+        for entry in entries {
+            match entry.name().map(|n| n.value()) {
+                Some("min") if min.is_none() => min = Some(value(entry)?),
+                Some("max") if max.is_none() => max = Some(value(entry)?),
+                _ => return None,
+            }
+        }
+
+        let range = min.unwrap_or(1)..=max.unwrap_or(i32::MAX);
+        return (!range.is_empty()).then_some(DiceRule::Range(range));
+    }
+
+    let values = entries.iter().map(value).collect::<Option<BTreeSet<_>>>()?;
+    (!values.is_empty()).then_some(DiceRule::Values(values))
+}
+
+/// Parses a `reroll` block, such as `reroll { max_attempts 10; delay_ms 500; }`.
+fn parse_reroll(node: &KdlNode, errors: &mut Vec<ConfigFileError>) -> RerollLimits {
+    let mut limits = RerollLimits::default();
+
+    let Some(children) = node.children() else {
+        return limits;
+    };
+
+    if let Some(node) = children.get("max_attempts") {
+        match node
+            .get(0)
+            .and_then(|v| v.as_integer())
+            .and_then(|n| u32::try_from(n).ok())
+            .filter(|&n| n > 0)
+        {
+            Some(n) => limits.max_attempts = n,
+            None => {
+                error!("Reroll max attempts key present in config but value is invalid");
+                errors.push(ConfigFileError::invalid("reroll.max_attempts", node));
+            }
+        }
+    }
+
+    if let Some(node) = children.get("delay_ms") {
+        match node
+            .get(0)
+            .and_then(|v| v.as_integer())
+            .and_then(|ms| u64::try_from(ms).ok())
+        {
+            Some(ms) => limits.delay = Duration::from_millis(ms),
+            None => {
+                error!("Reroll delay key present in config but value is invalid");
+                errors.push(ConfigFileError::invalid("reroll.delay_ms", node));
+            }
+        }
+    }
+
+    debug!(?limits, "Parsed reroll limits from config file");
+    limits
+}
+
+fn parse_prefixes(key: &str, node: &KdlNode) -> Result<Vec<String>, ConfigFileError> {
+    let prefixes = node
+        .entries()
+        .iter()
+        .filter(|e| e.name().is_none())
+        .map(|e| {
+            e.value()
+                .as_string()
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        })
+        .collect::<Option<Vec<_>>>();
+
+    match prefixes {
+        Some(prefixes) if !prefixes.is_empty() => {
+            debug!(?prefixes, "Parsed command prefixes from config file");
+            Ok(prefixes)
+        }
+        _ => {
+            error!(
+                key,
+                "Prefixes key present in config but values are missing or invalid"
+            );
+            Err(ConfigFileError::invalid(key, node))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dice::SlotRule;
+
+    #[test]
+    fn test_parse_prefixes() {
+        let config: ConfigFile = indoc::indoc! {r#"
+            prefixes "!" "." "//"
+            chat 1234 {
+                prefixes "?"
+            }
+        "#}
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.prefixes.unwrap(), ["!", ".", "//"]);
+        assert_eq!(config.chats[&1234].prefixes.as_ref().unwrap(), &["?"]);
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let config: ConfigFile = indoc::indoc! {r#"
+            alias "cr" "case r"
+            alias "shout" "case u $@"
+        "#}
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.aliases["cr"], "case r");
+        assert_eq!(config.aliases["shout"], "case u $@");
+    }
+
+    #[test]
+    fn test_parse_password_file() {
+        let config: ConfigFile = indoc::indoc! {r#"
+            telegram {
+                password_file "/run/secrets/tg-password"
+            }
+        "#}
+        .parse()
+        .unwrap();
+
+        assert!(matches!(
+            &config.profiles[0].password,
+            Some(Secret::File(path)) if path == Path::new("/run/secrets/tg-password")
+        ));
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let config: ConfigFile = indoc::indoc! {r#"
+            telegram {
+                api_id 1
+            }
+            telegram "work" {
+                api_id 2
+                session_filename "work.session"
+            }
+        "#}
+        .parse()
+        .unwrap();
+
+        let names: Vec<_> = config.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, [DEFAULT_PROFILE, "work"]);
+        assert_eq!(config.profiles[1].api_id, Some(2));
+        assert_eq!(
+            config.profiles[1].session_filename.as_deref(),
+            Some(Path::new("work.session"))
+        );
+    }
+
+    #[test]
+    fn test_parse_duplicate_profile_is_invalid() {
+        let result = indoc::indoc! {r#"
+            telegram "work"
+            telegram "work"
+        "#}
+        .parse::<ConfigFile>();
+        assert!(matches!(result, Err(ConfigFileError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_parse_rate_limits() {
+        let config: ConfigFile = indoc::indoc! {r#"
+            rate_limit {
+                per_chat 10 seconds=30
+                max_flood_wait 120
+            }
+        "#}
+        .parse()
+        .unwrap();
+
+        let limits = config.rate_limits.unwrap();
+        assert_eq!(limits.global, RateLimits::default().global);
+        assert_eq!(
+            limits.per_chat,
+            RateLimit {
+                requests: 10,
+                period: Duration::from_secs(30),
+            }
+        );
+        assert_eq!(limits.max_flood_wait, Duration::from_secs(120));
+
+        let result = "rate_limit { global 0 seconds=1; }".parse::<ConfigFile>();
+        assert!(matches!(result, Err(ConfigFileError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_parse_update_handling() {
+        let config: ConfigFile = "update_concurrency 4; drain_timeout 30".parse().unwrap();
+        assert_eq!(config.update_concurrency, Some(4));
+        assert_eq!(config.drain_timeout, Some(Duration::from_secs(30)));
+
+        let result = "update_concurrency 0".parse::<ConfigFile>();
+        assert!(matches!(result, Err(ConfigFileError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_parse_dice() {
+        let config: ConfigFile = indoc::indoc! {r#"
+            dice {
+                "🎲" 5 6
+                "🏀" min=4 max=5
+                "🎰" jackpot
+                "🎳" off
+            }
+            chat 123 {
+                dice {
+                    "🎲" off
                 }
             }
+        "#}
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.dice["🎲"], DiceRule::Values([5, 6].into()));
+        assert_eq!(config.dice["🏀"], DiceRule::Range(4..=5));
+        assert_eq!(config.dice["🎰"], DiceRule::Slot(SlotRule::Jackpot));
+        assert_eq!(config.dice["🎳"], DiceRule::Off);
+        assert_eq!(config.chats[&123].dice["🎲"], DiceRule::Off);
+
+        for invalid in [
+            r#""🎲" "six""#,
+            r#""🎲" min=5 max=4"#,
+            r#""🎲""#,
+            r#""🎲" jackpot"#,
+        ] {
+            let result = format!("dice {{ {}; }}", invalid).parse::<ConfigFile>();
+            assert!(
+                matches!(result, Err(ConfigFileError::InvalidValue { .. })),
+                "{invalid}"
+            );
         }
+    }
+
+    #[test]
+    fn test_parse_reroll() {
+        let config: ConfigFile = "reroll { max_attempts 5; delay_ms 250; }".parse().unwrap();
+        assert_eq!(
+            config.reroll,
+            Some(RerollLimits {
+                max_attempts: 5,
+                delay: Duration::from_millis(250),
+            })
+        );
+
+        let result = "reroll { max_attempts 0; }".parse::<ConfigFile>();
+        assert!(matches!(result, Err(ConfigFileError::InvalidValue { .. })));
+    }
 
-        Ok(config)
+    #[test]
+    fn test_parse_empty_prefix_is_invalid() {
+        let result = r#"prefixes "!" "" "#.parse::<ConfigFile>();
+        assert!(matches!(result, Err(ConfigFileError::InvalidValue { .. })));
     }
 }
@@ -0,0 +1,157 @@
+use std::{fmt::Write, fs, ops::Range, path::Path};
+
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, bail},
+};
+
+use crate::cli::Cli;
+
+use super::{ConfigFile, ConfigFileError, config_path};
+
+/// Validates the config file, printing every problem found in it.
+pub fn check(cli: &Cli) -> Result<()> {
+    let Some(path) = config_path(cli)? else {
+        bail!("No config file found, specify one with --config");
+    };
+
+    let source = fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Failed to read config file: {}", path.display()))?;
+
+    let problems: Vec<(Range<usize>, String)> = match ConfigFile::parse(&source) {
+        Ok((_, errors)) => errors
+            .into_iter()
+            .filter_map(|err| match err {
+                ConfigFileError::InvalidValue { key, span } => {
+                    Some((span, format!("invalid value for `{}`", key)))
+                }
+                _ => None,
+            })
+            .collect(),
+        Err(err) => err
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let span =
+                    diagnostic.span.offset()..diagnostic.span.offset() + diagnostic.span.len();
+                let message = diagnostic
+                    .message
+                    .clone()
+                    .or_else(|| diagnostic.label.clone())
+                    .unwrap_or_else(|| "invalid KDL".to_string());
+                (span, message)
+            })
+            .collect(),
+    };
+
+    if problems.is_empty() {
+        println!("{}: OK", path.display());
+        return Ok(());
+    }
+
+    for (span, message) in &problems {
+        print!("{}", render(&path, &source, span, message));
+    }
+
+    bail!(
+        "Found {} problem(s) in config file: {}",
+        problems.len(),
+        path.display()
+    );
+}
+
+/// Renders a problem as its location and message, followed by the offending
+/// line with the span underlined.
+fn render(path: &Path, source: &str, span: &Range<usize>, message: &str) -> String {
+    let start = span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    let line = &source[line_start..line_end];
+
+    let line_number = source[..start].matches('\n').count() + 1;
+    let column = source[line_start..start].chars().count() + 1;
+    let width = source[start..span.end.clamp(start, line_end)]
+        .chars()
+        .count()
+        .max(1);
+    let gutter = " ".repeat(line_number.to_string().len());
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{}:{}:{}: {}",
+        path.display(),
+        line_number,
+        column,
+        message
+    );
+    let _ = writeln!(out, "{} |", gutter);
+    let _ = writeln!(out, "{} | {}", line_number, line);
+    let _ = writeln!(
+        out,
+        "{} | {}{}",
+        gutter,
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_collects_every_invalid_value() {
+        let source = indoc::indoc! {r#"
+            log_level "loud"
+            telegram {
+                api_id "abc"
+            }
+            prefixes ""
+        "#};
+
+        let (_, errors) = ConfigFile::parse(source).unwrap();
+        let keys = errors
+            .iter()
+            .map(|err| match err {
+                ConfigFileError::InvalidValue { key, span } => {
+                    (key.as_str(), &source[span.clone()])
+                }
+                _ => panic!("unexpected error: {err:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0].0, "log_level");
+        assert!(keys[0].1.starts_with("log_level \"loud\""));
+        assert_eq!(keys[1].0, "telegram.api_id");
+        assert!(keys[1].1.starts_with("api_id \"abc\""));
+        assert_eq!(keys[2].0, "prefixes");
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "log_level 3\ntelegram {\n    api_id \"abc\"\n}\n";
+        let start = source.find("api_id").unwrap();
+        let rendered = render(
+            Path::new("config.kdl"),
+            source,
+            &(start..start + 12),
+            "invalid value for `telegram.api_id`",
+        );
+
+        assert_eq!(
+            rendered,
+            indoc::indoc! {r#"
+                config.kdl:3:5: invalid value for `telegram.api_id`
+                  |
+                3 |     api_id "abc"
+                  |     ^^^^^^^^^^^^
+            "#}
+        );
+    }
+}
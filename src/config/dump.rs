@@ -0,0 +1,329 @@
+use std::fmt::{Display, Write};
+
+use color_eyre::Result;
+
+use crate::cli::Cli;
+
+use super::{Config, ConfigFile, DEFAULT_PROFILE, Merged, ProfileFile, Secret};
+
+/// Shown in place of secret values.
+const REDACTED: &str = "\"<redacted>\"";
+
+const MISSING_API_ID: &str = "// api_id is missing, set it here or with --api-id";
+const MISSING_API_HASH: &str = "// api_hash is missing, set it here or with --api-hash";
+
+/// Column that source comments are aligned to.
+const SOURCE_COLUMN: usize = 48;
+
+/// Prints the effective config, merged from the config file, the environment
+/// and the command line, noting where each value came from.
+///
+/// Values that are missing are noted rather than treated as errors, so that
+/// incomplete configs can be dumped too.
+pub fn dump(cli: &Cli) -> Result<()> {
+    let merged = Config::merge(cli)?;
+
+    print!("{}", render(cli, &merged));
+
+    Ok(())
+}
+
+fn render(cli: &Cli, merged: &Merged) -> String {
+    let config = &merged.config;
+    let mut dump = Dump::default();
+    let empty = ConfigFile::default();
+    let file = merged.file.as_ref().unwrap_or(&empty);
+    let in_block = |key: &str| file.block_keys.contains(key);
+
+    match config.path() {
+        Some(path) => dump.line(
+            format_args!("config_file {:?}", path),
+            cli.origin("config")
+                .unwrap_or_else(|| "default location".to_string()),
+        ),
+        None => dump.line("// no config file", String::new()),
+    }
+
+    let log_level_origin = cli
+        .origin("log_level")
+        .or_else(|| cli.origin("verbose"))
+        .or_else(|| cli.origin("quiet"));
+    dump.line(
+        format_args!(
+            "log_level {:?}",
+            config.log_level().unwrap_or_default().to_string()
+        ),
+        source(log_level_origin, file.log_level.is_some()),
+    );
+    dump.line(
+        format_args!("update_concurrency {}", config.update_concurrency()),
+        source(None, file.update_concurrency.is_some()),
+    );
+    dump.line(
+        format_args!("drain_timeout {}", config.drain_timeout().as_secs()),
+        source(None, file.drain_timeout.is_some()),
+    );
+
+    let limits = merged.rate_limits;
+    dump.open("rate_limit");
+    for (key, limit) in [("global", limits.global), ("per_chat", limits.per_chat)] {
+        dump.line(
+            format_args!(
+                "{} {} seconds={}",
+                key,
+                limit.requests,
+                limit.period.as_secs()
+            ),
+            source(None, in_block(&format!("rate_limit.{}", key))),
+        );
+    }
+    dump.line(
+        format_args!("max_flood_wait {}", limits.max_flood_wait.as_secs()),
+        source(None, in_block("rate_limit.max_flood_wait")),
+    );
+    dump.close();
+
+    dump.line(
+        format_args!("prefixes {}", quoted(&config.prefixes)),
+        source(None, file.prefixes.is_some()),
+    );
+
+    dump.open("dice");
+    for (emoticon, rule) in &config.dice {
+        dump.line(
+            format_args!("{:?} {}", emoticon, rule),
+            source(None, file.dice.contains_key(emoticon)),
+        );
+    }
+    dump.close();
+
+    let reroll = config.reroll();
+    dump.open("reroll");
+    dump.line(
+        format_args!("max_attempts {}", reroll.max_attempts),
+        source(None, in_block("reroll.max_attempts")),
+    );
+    dump.line(
+        format_args!("delay_ms {}", reroll.delay.as_millis()),
+        source(None, in_block("reroll.delay_ms")),
+    );
+    dump.close();
+
+    for (name, expansion) in config.aliases() {
+        dump.line(
+            format_args!("alias {:?} {:?}", name, expansion),
+            source(None, true),
+        );
+    }
+
+    let mut chats = config.chats.iter().collect::<Vec<_>>();
+    chats.sort_by_key(|(id, _)| **id);
+    for (id, chat) in chats {
+        dump.open(format_args!("chat {}", id));
+        if let Some(prefixes) = &chat.prefixes {
+            dump.line(
+                format_args!("prefixes {}", quoted(prefixes)),
+                source(None, true),
+            );
+        }
+        if !chat.dice.is_empty() {
+            dump.open("dice");
+            for (emoticon, rule) in &chat.dice {
+                dump.line(format_args!("{:?} {}", emoticon, rule), source(None, true));
+            }
+            dump.close();
+        }
+        dump.close();
+    }
+
+    for profile in &merged.profiles {
+        let empty_profile = ProfileFile::default();
+        let profile_file = file
+            .profiles
+            .iter()
+            .find(|p| p.name == profile.name)
+            .unwrap_or(&empty_profile);
+        let file_storage = profile_file.storage_filename.is_some()
+            || (profile.name == DEFAULT_PROFILE && file.storage_filename.is_some());
+
+        dump.open(format_args!("telegram {:?}", profile.name));
+        match profile.api_id {
+            Some(api_id) => dump.line(
+                format_args!("api_id {}", api_id),
+                source(cli.origin("api_id"), profile_file.api_id.is_some()),
+            ),
+            None => dump.line(MISSING_API_ID, String::new()),
+        }
+        match profile.api_hash {
+            Some(_) => dump.line(
+                format_args!("api_hash {}", REDACTED),
+                source(cli.origin("api_hash"), profile_file.api_hash.is_some()),
+            ),
+            None => dump.line(MISSING_API_HASH, String::new()),
+        }
+        if let Some(phone_number) = &profile.phone_number {
+            dump.line(
+                format_args!("phone_number {:?}", phone_number),
+                source(
+                    cli.origin("phone_number"),
+                    profile_file.phone_number.is_some(),
+                ),
+            );
+        }
+        for (key, secret, in_file) in [
+            (
+                "password",
+                &profile.password,
+                profile_file.password.is_some(),
+            ),
+            (
+                "session_passphrase",
+                &profile.session_passphrase,
+                profile_file.session_passphrase.is_some(),
+            ),
+        ] {
+            let file_key = format!("{}_file", key);
+            match secret {
+                Some(Secret::Value(_)) => {
+                    dump.line(
+                        format_args!("{} {}", key, REDACTED),
+                        source(cli.origin(key), in_file),
+                    );
+                }
+                Some(Secret::File(path)) => {
+                    dump.line(
+                        format_args!("{} {:?}", file_key, path),
+                        source(cli.origin(&file_key), in_file),
+                    );
+                }
+                None => {}
+            }
+        }
+        if let Some(session_filename) = &profile.session_filename {
+            dump.line(
+                format_args!("session_filename {:?}", session_filename),
+                source(
+                    cli.origin("session"),
+                    profile_file.session_filename.is_some(),
+                ),
+            );
+        }
+        if let Some(storage_filename) = &profile.storage_filename {
+            dump.line(
+                format_args!("storage_filename {:?}", storage_filename),
+                source(cli.origin("storage"), file_storage),
+            );
+        }
+        dump.close();
+    }
+
+    dump.out
+}
+
+/// Picks the command line or environment origin of a value if there is one,
+/// otherwise whether it came from the config file or is the default.
+fn source(origin: Option<String>, in_file: bool) -> String {
+    origin.unwrap_or_else(|| match in_file {
+        true => "config file".to_string(),
+        false => "default".to_string(),
+    })
+}
+
+fn quoted(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{:?}", v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Builds KDL-like output with a trailing comment on each line.
+#[derive(Default)]
+struct Dump {
+    out: String,
+    depth: usize,
+}
+
+impl Dump {
+    fn line(&mut self, text: impl Display, source: String) {
+        let text = format!("{}{}", "    ".repeat(self.depth), text);
+        let _ = match source.is_empty() {
+            true => writeln!(self.out, "{}", text),
+            false => writeln!(
+                self.out,
+                "{:<width$} // {}",
+                text,
+                source,
+                width = SOURCE_COLUMN
+            ),
+        };
+    }
+
+    fn open(&mut self, text: impl Display) {
+        let _ = writeln!(self.out, "{}{} {{", "    ".repeat(self.depth), text);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        let _ = writeln!(self.out, "{}}}", "    ".repeat(self.depth));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_render_incomplete_config() {
+        let path = std::env::temp_dir().join(format!("shabby-dump-{}.kdl", std::process::id()));
+        std::fs::write(
+            &path,
+            indoc::indoc! {r#"
+                prefixes "!" "."
+                rate_limit {
+                    per_chat 5 seconds=2
+                }
+                reroll {
+                    delay_ms 100
+                }
+                telegram {
+                    api_id 12345
+                    password "hunter2"
+                }
+            "#},
+        )
+        .unwrap();
+
+        let cli = Cli::try_parse_from(["shabby", "--config", path.to_str().unwrap()]).unwrap();
+        let merged = Config::merge(&cli);
+        let validated = Config::from_cli(&cli);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(validated.unwrap_err().to_string().contains("API hash"));
+        let rendered = render(&cli, &merged.unwrap());
+        let source_of = |key: &str| {
+            rendered
+                .lines()
+                .find(|line| line.trim_start().starts_with(key))
+                .and_then(|line| line.split("// ").nth(1))
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(rendered.contains(r#"prefixes "!" "."#));
+        assert!(rendered.contains("telegram \"default\" {\n"));
+        assert!(rendered.contains("    api_id 12345"));
+        assert!(rendered.contains(MISSING_API_HASH));
+        assert!(rendered.contains(&format!("    password {}", REDACTED)));
+        assert!(!rendered.contains("hunter2"));
+
+        assert_eq!(source_of("global "), "default");
+        assert_eq!(source_of("per_chat "), "config file");
+        assert_eq!(source_of("max_flood_wait "), "default");
+        assert_eq!(source_of("max_attempts "), "default");
+        assert_eq!(source_of("delay_ms "), "config file");
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tokio::time::{Interval, MissedTickBehavior};
+use tracing::debug;
+
+/// How often the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Notices when the config file changes by polling its metadata.
+///
+/// A change is only reported once the file has looked the same for a whole
+/// interval, so that a file still being written isn't picked up halfway.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    interval: Interval,
+    /// How the file looked when the current config was loaded from it.
+    loaded: Option<Stamp>,
+    /// How the file looked the last time it was checked.
+    seen: Option<Stamp>,
+}
+
+/// When a file was last modified and how big it was then.
+type Stamp = (SystemTime, u64);
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        Self::with_interval(path, POLL_INTERVAL)
+    }
+
+    fn with_interval(path: PathBuf, period: Duration) -> Self {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let stamp = stamp(&path);
+
+        Self {
+            path,
+            interval,
+            loaded: stamp,
+            seen: stamp,
+        }
+    }
+
+    /// Waits until the file has changed since the last time this returned.
+    pub async fn changed(&mut self) {
+        loop {
+            self.interval.tick().await;
+            let stamp = stamp(&self.path);
+
+            if stamp != self.seen {
+                self.seen = stamp;
+                continue;
+            }
+
+            if stamp != self.loaded {
+                debug!(path = %self.path.display(), "Config file changed");
+                self.loaded = stamp;
+                return;
+            }
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watcher_notices_changes() {
+        let path = std::env::temp_dir().join(format!("shabby-watch-{}.kdl", std::process::id()));
+        fs::write(&path, "prefixes \"!\"").unwrap();
+
+        let mut watcher = ConfigWatcher::with_interval(path.clone(), Duration::from_millis(10));
+        let unchanged = tokio::time::timeout(Duration::from_millis(100), watcher.changed()).await;
+        assert!(unchanged.is_err());
+
+        fs::write(&path, "prefixes \"!\" \".\"").unwrap();
+        let changed = tokio::time::timeout(Duration::from_secs(1), watcher.changed()).await;
+        fs::remove_file(&path).unwrap();
+        assert!(changed.is_ok());
+    }
+}
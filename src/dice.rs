@@ -0,0 +1,387 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display},
+    ops::RangeInclusive,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+/// The slot machine emoticon, whose value encodes three reels.
+pub const SLOT_MACHINE: &str = "🎰";
+
+/// How long a sent dice is remembered while waiting for it to come back as an update.
+const OWN_DICE_TTL: Duration = Duration::from_secs(300);
+
+/// Decides which values of a dice emoticon are kept, with anything else rerolled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiceRule {
+    /// Keeps only the listed values.
+    Values(BTreeSet<i32>),
+    /// Keeps values within the range.
+    Range(RangeInclusive<i32>),
+    /// Keeps slot machine outcomes whose reels match.
+    Slot(SlotRule),
+    /// Never rerolls.
+    Off,
+}
+
+/// A symbol on a slot machine reel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    Bar,
+    Grapes,
+    Lemon,
+    Seven,
+}
+
+/// The symbols a slot machine stopped at, from left to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reels(pub [Symbol; 3]);
+
+/// Which slot machine outcomes to accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotRule {
+    /// Three sevens.
+    Jackpot,
+    ThreeOfAKind,
+    /// At least two reels with the same symbol.
+    TwoOfAKind,
+    AnyBar,
+    AnySeven,
+    /// Specific symbols per reel, where `None` matches any symbol.
+    Pattern([Option<Symbol>; 3]),
+}
+
+/// Dice the bot sent itself, remembered until they come back as new message
+/// updates so that they aren't mistaken for dice sent by hand.
+#[derive(Debug, Default)]
+pub struct OwnDice {
+    sent: Mutex<HashMap<(i64, i32), (Instant, OwnRoll)>>,
+}
+
+/// Why the bot sent a dice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnRoll {
+    /// Rerolled a dice that didn't match its rule, with every value rolled so
+    /// far in the chain.
+    Reroll(Vec<i32>),
+    /// Rolled by a command, which handles the outcome itself.
+    Command,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DiceRuleError {
+    #[error("Expected values like `5,6`, a range like `4..5`, or `off`")]
+    Invalid,
+
+    #[error(
+        "Expected `jackpot`, `three-of-a-kind`, `two-of-a-kind`, `any-bar`, `any-seven` or reels like `bar,*,seven`"
+    )]
+    InvalidSlot,
+
+    #[error("Slot machine rules only apply to {SLOT_MACHINE}")]
+    NotSlotMachine,
+}
+
+impl DiceRule {
+    /// Parses a rule as written in a command, interpreting names of slot
+    /// machine rules only for the slot machine.
+    pub fn parse(emoticon: &str, s: &str) -> Result<Self, DiceRuleError> {
+        let s = s.trim();
+
+        if s == "off" {
+            return Ok(DiceRule::Off);
+        }
+
+        if emoticon == SLOT_MACHINE
+            && let Ok(rule) = s.parse()
+        {
+            return Ok(DiceRule::Slot(rule));
+        }
+
+        if let Some((start, end)) = s.split_once("..") {
+            let end = end.strip_prefix('=').unwrap_or(end);
+            let range = parse_value(start)?..=parse_value(end)?;
+            return match range.is_empty() {
+                true => Err(DiceRuleError::Invalid),
+                false => Ok(DiceRule::Range(range)),
+            };
+        }
+
+        match s.split(',').map(parse_value).collect() {
+            Ok(values) => Ok(DiceRule::Values(values)),
+            Err(_) if emoticon == SLOT_MACHINE => Err(DiceRuleError::InvalidSlot),
+            Err(_) if s.parse::<SlotRule>().is_ok() => Err(DiceRuleError::NotSlotMachine),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn accepts(&self, value: i32) -> bool {
+        match self {
+            DiceRule::Values(values) => values.contains(&value),
+            DiceRule::Range(range) => range.contains(&value),
+            DiceRule::Slot(rule) => Reels::decode(value).is_some_and(|reels| rule.accepts(reels)),
+            DiceRule::Off => true,
+        }
+    }
+}
+
+fn parse_value(s: &str) -> Result<i32, DiceRuleError> {
+    s.trim().parse().map_err(|_| DiceRuleError::Invalid)
+}
+
+/// Formats the rule the way it is written in the config file.
+impl Display for DiceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiceRule::Values(values) => {
+                let values = values.iter().map(i32::to_string).collect::<Vec<_>>();
+                write!(f, "{}", values.join(" "))
+            }
+            DiceRule::Range(range) => write!(f, "min={} max={}", range.start(), range.end()),
+            DiceRule::Slot(rule) => write!(f, "{:?}", rule.to_string()),
+            DiceRule::Off => write!(f, "off"),
+        }
+    }
+}
+
+impl Symbol {
+    const ALL: [Symbol; 4] = [Symbol::Bar, Symbol::Grapes, Symbol::Lemon, Symbol::Seven];
+
+    fn name(self) -> &'static str {
+        match self {
+            Symbol::Bar => "bar",
+            Symbol::Grapes => "grapes",
+            Symbol::Lemon => "lemon",
+            Symbol::Seven => "seven",
+        }
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = DiceRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "7" => Ok(Symbol::Seven),
+            _ => Symbol::ALL
+                .into_iter()
+                .find(|symbol| symbol.name() == s)
+                .ok_or(DiceRuleError::InvalidSlot),
+        }
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let emoji = match self {
+            Symbol::Bar => "🅱️",
+            Symbol::Grapes => "🍇",
+            Symbol::Lemon => "🍋",
+            Symbol::Seven => "7️⃣",
+        };
+        write!(f, "{}", emoji)
+    }
+}
+
+impl Reels {
+    /// Decodes a slot machine value from 1 to 64, which counts through every
+    /// combination in base 4 with the left reel changing fastest.
+    pub fn decode(value: i32) -> Option<Self> {
+        if !(1..=64).contains(&value) {
+            return None;
+        }
+
+        let index = (value - 1) as usize;
+        Some(Reels(
+            [0, 1, 2].map(|reel| Symbol::ALL[(index >> (2 * reel)) & 3]),
+        ))
+    }
+
+    fn count(self, symbol: Symbol) -> usize {
+        self.0.iter().filter(|&&s| s == symbol).count()
+    }
+}
+
+impl Display for Reels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [left, middle, right] = self.0;
+        write!(f, "{} {} {}", left, middle, right)
+    }
+}
+
+impl SlotRule {
+    pub fn accepts(self, reels: Reels) -> bool {
+        let [left, middle, right] = reels.0;
+
+        match self {
+            SlotRule::Jackpot => reels.count(Symbol::Seven) == 3,
+            SlotRule::ThreeOfAKind => left == middle && middle == right,
+            SlotRule::TwoOfAKind => left == middle || middle == right || left == right,
+            SlotRule::AnyBar => reels.count(Symbol::Bar) > 0,
+            SlotRule::AnySeven => reels.count(Symbol::Seven) > 0,
+            SlotRule::Pattern(pattern) => pattern
+                .iter()
+                .zip(reels.0)
+                .all(|(expected, symbol)| expected.is_none_or(|e| e == symbol)),
+        }
+    }
+}
+
+impl FromStr for SlotRule {
+    type Err = DiceRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jackpot" | "777" => Ok(SlotRule::Jackpot),
+            "three-of-a-kind" | "triple" => Ok(SlotRule::ThreeOfAKind),
+            "two-of-a-kind" | "pair" => Ok(SlotRule::TwoOfAKind),
+            "any-bar" => Ok(SlotRule::AnyBar),
+            "any-seven" => Ok(SlotRule::AnySeven),
+            _ => {
+                let symbols = s
+                    .split(',')
+                    .map(|symbol| match symbol.trim() {
+                        "*" => Ok(None),
+                        symbol => symbol.parse().map(Some),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                symbols
+                    .try_into()
+                    .map(SlotRule::Pattern)
+                    .map_err(|_| DiceRuleError::InvalidSlot)
+            }
+        }
+    }
+}
+
+impl Display for SlotRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlotRule::Jackpot => write!(f, "jackpot"),
+            SlotRule::ThreeOfAKind => write!(f, "three-of-a-kind"),
+            SlotRule::TwoOfAKind => write!(f, "two-of-a-kind"),
+            SlotRule::AnyBar => write!(f, "any-bar"),
+            SlotRule::AnySeven => write!(f, "any-seven"),
+            SlotRule::Pattern(pattern) => {
+                let symbols = pattern
+                    .map(|symbol| symbol.map_or("*", Symbol::name))
+                    .join(",");
+                write!(f, "{}", symbols)
+            }
+        }
+    }
+}
+
+impl OwnDice {
+    pub fn insert(&self, chat_id: i64, message_id: i32, roll: OwnRoll) {
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        sent.retain(|_, (at, _)| at.elapsed() < OWN_DICE_TTL);
+        sent.insert((chat_id, message_id), (Instant::now(), roll));
+    }
+
+    /// Forgets a dice, returning why it was sent if the bot sent it.
+    pub fn take(&self, chat_id: i64, message_id: i32) -> Option<OwnRoll> {
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        sent.remove(&(chat_id, message_id)).map(|(_, roll)| roll)
+    }
+}
+
+/// The rules used for emoticons that the config doesn't mention, which keep
+/// only the best outcome of each.
+pub fn default_rules() -> BTreeMap<String, DiceRule> {
+    [
+        ("🎲", DiceRule::Values([6].into())),
+        ("🏀", DiceRule::Range(4..=5)),
+        ("🎯", DiceRule::Values([6].into())),
+        ("⚽", DiceRule::Range(3..=5)),
+        ("🎳", DiceRule::Values([6].into())),
+        (SLOT_MACHINE, DiceRule::Off),
+    ]
+    .into_iter()
+    .map(|(emoticon, rule)| (emoticon.to_string(), rule))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rule_accepts() {
+        let rules = default_rules();
+        assert!(rules["🎲"].accepts(6));
+        assert!(!rules["🎲"].accepts(5));
+        assert!(rules["🏀"].accepts(4));
+        assert!(!rules["🏀"].accepts(3));
+        assert!(DiceRule::Off.accepts(1));
+    }
+
+    #[test]
+    fn test_decode_reels() {
+        use Symbol::*;
+
+        assert_eq!(Reels::decode(1), Some(Reels([Bar, Bar, Bar])));
+        assert_eq!(Reels::decode(22), Some(Reels([Grapes, Grapes, Grapes])));
+        assert_eq!(Reels::decode(43), Some(Reels([Lemon, Lemon, Lemon])));
+        assert_eq!(Reels::decode(64), Some(Reels([Seven, Seven, Seven])));
+        assert_eq!(Reels::decode(2), Some(Reels([Grapes, Bar, Bar])));
+        assert_eq!(Reels::decode(0), None);
+        assert_eq!(Reels::decode(65), None);
+    }
+
+    #[test]
+    fn test_slot_rules() {
+        let jackpot = DiceRule::parse(SLOT_MACHINE, "jackpot").unwrap();
+        assert!(jackpot.accepts(64));
+        assert!(!jackpot.accepts(43));
+
+        let triple = DiceRule::parse(SLOT_MACHINE, "three-of-a-kind").unwrap();
+        assert!([1, 22, 43, 64].iter().all(|&v| triple.accepts(v)));
+        assert_eq!((1..=64).filter(|&v| triple.accepts(v)).count(), 4);
+
+        let any_bar = DiceRule::parse(SLOT_MACHINE, "any-bar").unwrap();
+        assert_eq!((1..=64).filter(|&v| any_bar.accepts(v)).count(), 64 - 27);
+
+        let pattern = DiceRule::parse(SLOT_MACHINE, "7,*,seven").unwrap();
+        assert_eq!(pattern.to_string(), "\"seven,*,seven\"");
+        assert_eq!((1..=64).filter(|&v| pattern.accepts(v)).count(), 4);
+    }
+
+    #[test]
+    fn test_own_dice() {
+        let own = OwnDice::default();
+        own.insert(1, 10, OwnRoll::Reroll(vec![2, 3]));
+
+        assert_eq!(own.take(2, 10), None);
+        assert_eq!(own.take(1, 10), Some(OwnRoll::Reroll(vec![2, 3])));
+        assert_eq!(own.take(1, 10), None);
+
+        own.insert(1, 11, OwnRoll::Command);
+        assert_eq!(own.take(1, 11), Some(OwnRoll::Command));
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(
+            DiceRule::parse("🎲", "5,6"),
+            Ok(DiceRule::Values([5, 6].into()))
+        );
+        assert_eq!(DiceRule::parse("🏀", "4..=5"), Ok(DiceRule::Range(4..=5)));
+        assert_eq!(DiceRule::parse("🏀", "4..5"), Ok(DiceRule::Range(4..=5)));
+        assert_eq!(DiceRule::parse("🎲", "off"), Ok(DiceRule::Off));
+        assert_eq!(DiceRule::parse("🎲", "5..4"), Err(DiceRuleError::Invalid));
+        assert_eq!(
+            DiceRule::parse("🎲", "jackpot"),
+            Err(DiceRuleError::NotSlotMachine)
+        );
+        assert_eq!(
+            DiceRule::parse(SLOT_MACHINE, "cherries"),
+            Err(DiceRuleError::InvalidSlot)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::HashMap, sync::Arc};
+
+use grammers_client::Update;
+use tokio::{
+    sync::{
+        Semaphore,
+        oneshot::{self, error::TryRecvError},
+    },
+    task::JoinSet,
+};
+use tracing::{Instrument, Span, debug, error};
+
+use crate::{Bot, handle_update};
+
+/// Handles updates on spawned tasks, running at most a configured number at
+/// once while keeping the updates of each chat in the order they arrived.
+///
+/// The limit follows config reloads, with updates dispatched before a change
+/// still counted against the old limit.
+pub struct Dispatcher {
+    bot: Arc<Bot>,
+    concurrency: usize,
+    permits: Arc<Semaphore>,
+    tasks: JoinSet<bool>,
+    /// Completes once the most recent update dispatched for the chat is handled.
+    chats: HashMap<i64, oneshot::Receiver<()>>,
+}
+
+impl Dispatcher {
+    pub fn new(bot: Arc<Bot>) -> Self {
+        let concurrency = bot.config().update_concurrency();
+
+        Self {
+            bot,
+            concurrency,
+            permits: Arc::new(Semaphore::new(concurrency)),
+            tasks: JoinSet::new(),
+            chats: HashMap::new(),
+        }
+    }
+
+    /// Spawns a task handling the update once earlier updates from its chat are handled.
+    pub fn dispatch(&mut self, update: Update) {
+        let concurrency = self.bot.config().update_concurrency();
+        if concurrency != self.concurrency {
+            debug!(
+                from = self.concurrency,
+                to = concurrency,
+                "Changing update concurrency"
+            );
+            self.concurrency = concurrency;
+            self.permits = Arc::new(Semaphore::new(concurrency));
+        }
+
+        let (done, handled) = oneshot::channel();
+        let previous = match chat_id(&update) {
+            Some(chat_id) => {
+                if !self.chats.contains_key(&chat_id) {
+                    self.chats.retain(|_, handled| {
+                        matches!(handled.try_recv(), Err(TryRecvError::Empty))
+                    });
+                }
+                self.chats.insert(chat_id, handled)
+            }
+            None => None,
+        };
+
+        let bot = self.bot.clone();
+        let permits = self.permits.clone();
+
+        self.tasks.spawn(
+            async move {
+                // Dropping the sender signals the next update, even if this task is aborted
+                let _done = done;
+
+                if let Some(previous) = previous {
+                    let _ = previous.await;
+                }
+
+                let Ok(_permit) = permits.acquire().await else {
+                    return false;
+                };
+
+                match handle_update(&bot, update).await {
+                    Ok(quit) => quit,
+                    Err(err) => {
+                        error!(?err, "Error handling update");
+                        false
+                    }
+                }
+            }
+            .instrument(Span::current()),
+        );
+    }
+
+    /// Waits for the next handled update, returning whether it asked to quit.
+    ///
+    /// Never completes while no updates are being handled.
+    pub async fn next_handled(&mut self) -> bool {
+        match self.tasks.join_next().await {
+            Some(Ok(quit)) => quit,
+            Some(Err(err)) => {
+                if err.is_panic() {
+                    error!(?err, "Update handler panicked");
+                }
+                false
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Waits for all updates that are still being handled.
+    pub async fn finish(mut self) {
+        while self.tasks.join_next().await.is_some() {}
+    }
+}
+
+fn chat_id(update: &Update) -> Option<i64> {
+    match update {
+        Update::NewMessage(message) | Update::MessageEdited(message) => Some(message.chat().id()),
+        _ => None,
+    }
+}
@@ -1,210 +1,637 @@
-use std::{env, io::Write};
+use std::{
+    env,
+    sync::{Arc, RwLock},
+};
 
-use clap::Parser;
+use clap::error::ErrorKind;
 use color_eyre::{Result, eyre::WrapErr};
 use grammers_client::{
-    Client, Config as GrammersConfig, InputMessage, Update,
+    Client, InputMessage, InvocationError, Update,
     grammers_tl_types::types::MessageMediaDice,
     session::Session,
     types::{Chat, Media, Message, User, media::Dice},
 };
-use tokio::signal;
-use tracing::{error, info, warn};
-
-use self::{cli::Cli, config::Config, logging::LogState};
+use tokio::{sync::watch, task::JoinSet, time::Instant};
+use tracing::{Instrument, Span, debug, error, info, info_span, warn};
+
+pub use self::auth::AuthError;
+
+use self::{
+    cli::{Cli, CliCommand, ConfigCommand, SessionCommand},
+    command::{AliasStore, CommandRegistry},
+    config::{Config, ConfigWatcher, Profile},
+    dice::{DiceRule, OwnDice, OwnRoll},
+    dispatch::Dispatcher,
+    logging::LogState,
+    rate_limit::RateLimiter,
+    scheduler::Scheduler,
+    sed::Substitution,
+    session::{SessionError, SessionFile},
+    signals::{Signal, Signals},
+    storage::Storage,
+    supervisor::Backoff,
+};
 
+mod auth;
 mod cli;
-mod command;
+pub mod command;
 mod config;
+mod dice;
 mod dirs;
+mod dispatch;
 mod logging;
-
-struct Bot {
-    client: Client,
+mod rate_limit;
+mod roll;
+mod scheduler;
+mod sed;
+mod session;
+mod signals;
+mod storage;
+mod supervisor;
+
+/// How many messages to look back through for one to apply a substitution to.
+const SED_SEARCH_LIMIT: usize = 50;
+
+pub struct Bot {
+    client: RwLock<Client>,
     me: User,
+    config: RwLock<Arc<Config>>,
+    profile: Profile,
+    session_file: SessionFile,
+    commands: Arc<CommandRegistry>,
+    aliases: AliasStore,
+    storage: Storage,
+    scheduler: Scheduler,
+    limiter: RateLimiter,
+    own_dice: OwnDice,
 }
 
-struct Context {
-    chat: Chat,
-    message: Message,
+/// The message (and chat it was sent in) that a command is being handled for.
+#[derive(Clone)]
+pub struct Context<'a> {
+    pub bot: &'a Bot,
+    pub chat: Chat,
+    pub message: Message,
+    /// Text piped in from the previous command in a pipeline.
+    pub input: Option<String>,
+}
+
+impl Bot {
+    /// Gets the client, which is replaced whenever the bot reconnects.
+    pub fn client(&self) -> Client {
+        self.client.read().unwrap().clone()
+    }
+
+    pub fn me(&self) -> &User {
+        &self.me
+    }
+
+    /// Gets the config, which is replaced whenever it is reloaded.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Gets the profile of the account this bot runs as.
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    pub fn commands(&self) -> &CommandRegistry {
+        &self.commands
+    }
+
+    pub fn aliases(&self) -> &AliasStore {
+        &self.aliases
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Gets the rate limiter that outgoing requests should go through.
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    /// Gets the dice this bot sent that haven't come back as updates yet.
+    pub fn own_dice(&self) -> &OwnDice {
+        &self.own_dice
+    }
+}
+
+impl Context<'_> {
+    /// Gets the text a command should operate on when none is given as arguments.
+    ///
+    /// This is the piped input if there is any, otherwise the replied-to message.
+    pub async fn input_text(&self) -> Result<Option<String>, grammers_client::InvocationError> {
+        if let Some(input) = &self.input {
+            return Ok(Some(input.clone()));
+        }
+
+        Ok(self
+            .message
+            .get_reply()
+            .await?
+            .map(|m| m.text().to_string()))
+    }
 }
 
 pub async fn run() -> Result<LogState> {
-    let cli = Cli::try_parse()?;
+    run_with_commands(CommandRegistry::with_builtins()).await
+}
+
+/// Runs shabby with a custom set of chat commands.
+pub async fn run_with_commands(commands: CommandRegistry) -> Result<LogState> {
+    let cli = Cli::try_parse_with_sources()?;
     let log_state = logging::init(cli.log_level().unwrap_or_default())?;
 
     let cwd = env::current_dir().wrap_err("Failed to get current working directory")?;
 
     info!(cwd = %cwd.display(), "Initializing");
 
+    // Inspecting the config has to work even when it can't be loaded
+    if let Some(CliCommand::Config(action)) = cli.command {
+        match action {
+            ConfigCommand::Check => config::check(&cli)?,
+            ConfigCommand::Dump => config::dump(&cli)?,
+        }
+        return Ok(log_state);
+    }
+
     let config = Config::from_cli(&cli)?;
     if let Some(config_log_level) = config.log_level() {
         log_state.set_level_filter(config_log_level)?;
     }
-    let session_path = config.session_filename();
-
-    if let Some(session_dir) = session_path.parent() {
-        if session_dir.to_string_lossy() != "" && !session_dir.exists() {
-            std::fs::create_dir_all(session_dir).wrap_err_with(|| {
-                format!(
-                    "Failed to create session directory: {}",
-                    session_dir.display()
-                )
-            })?;
+
+    let profiles = config.profiles();
+
+    match cli.command.unwrap_or_default() {
+        CliCommand::Login(args) => {
+            for profile in profiles {
+                if profiles.len() > 1 {
+                    println!("Profile {}:", profile.name());
+                }
+
+                auth::login(profile, args)
+                    .instrument(profile_span(profile))
+                    .await?;
+            }
         }
+        CliCommand::Logout => {
+            for profile in profiles {
+                if profiles.len() > 1 {
+                    println!("Profile {}:", profile.name());
+                }
+
+                auth::logout(profile)
+                    .instrument(profile_span(profile))
+                    .await?;
+            }
+        }
+        CliCommand::Session(action) => {
+            for profile in profiles {
+                let _span = profile_span(profile).entered();
+                match action {
+                    SessionCommand::Encrypt => session::encrypt_file(profile)?,
+                    SessionCommand::Decrypt => session::decrypt_file(profile)?,
+                }
+            }
+        }
+        CliCommand::Config(_) => unreachable!("config commands are handled before loading it"),
+        CliCommand::Run => run_bots(&cli, &log_state, config, commands).await?,
     }
-    let session =
-        Session::load_file_or_create(session_path).wrap_err("Failed to load or create session")?;
 
-    info!("Using session file: {}", session_path.display());
+    Ok(log_state)
+}
 
-    let client = Client::connect(GrammersConfig {
-        api_id: config.api_id(),
-        api_hash: config.api_hash().to_string(),
-        session,
-        params: Default::default(),
-    })
-    .await
-    .wrap_err("Failed to connect to Telegram")?;
+fn profile_span(profile: &Profile) -> Span {
+    info_span!("profile", name = profile.name())
+}
 
-    if !client
-        .is_authorized()
-        .await
-        .wrap_err("Failed to check authorization")?
-    {
-        info!("Requesting token SMS");
-        let token = client
-            .request_login_code(config.phone_number())
-            .await
-            .wrap_err("Failed to request login code")?;
+/// Runs a bot for every profile until all of them quit or the process is asked
+/// to shut down, reloading the config on SIGHUP.
+///
+/// Fails before starting any bot if one of the profiles is not logged in.
+/// The sessions of all connected bots are saved on the way out either way.
+async fn run_bots(
+    cli: &Cli,
+    log_state: &LogState,
+    config: Config,
+    commands: CommandRegistry,
+) -> Result<()> {
+    let mut signals = Signals::new().wrap_err("Failed to listen for signals")?;
+    let mut config = Arc::new(config);
+    let commands = Arc::new(commands);
+    let mut bots = Vec::new();
+
+    for profile in config.profiles() {
+        let bot = connect_bot(config.clone(), profile.clone(), commands.clone())
+            .instrument(profile_span(profile))
+            .await;
+
+        match bot {
+            Ok(bot) => bots.push(Arc::new(bot)),
+            Err(err) => {
+                save_sessions(&bots)?;
+                return Err(err);
+            }
+        }
+    }
 
-        print!("Enter the code you received: ");
-        std::io::stdout().flush()?;
-        let mut code = String::new();
-        std::io::stdin().read_line(&mut code)?;
+    println!("Press Ctrl+C to exit");
 
-        let code = code.trim().to_string();
+    let mut watcher = config
+        .path()
+        .map(|path| ConfigWatcher::new(path.to_path_buf()));
+
+    let (shutdown, shutdown_rx) = watch::channel(false);
+    let mut tasks = JoinSet::new();
+    for bot in &bots {
+        let bot = bot.clone();
+        let shutdown = shutdown_rx.clone();
+        let span = profile_span(&bot.profile);
+        tasks.spawn(async move { run_bot(&bot, shutdown).await }.instrument(span));
+    }
 
-        let user = match client
-            .sign_in(&token, &code)
-            .await
-            .wrap_err("Failed to sign in")
-        {
-            Ok(user) => {
-                if let Err(err) = client
-                    .session()
-                    .save_to_file(config.session_filename())
-                    .wrap_err("Failed to save session")
-                {
-                    client.sign_out().await.wrap_err("Failed to sign out")?;
-                    return Err(err);
-                };
-
-                user
+    loop {
+        tokio::select! {
+            signal = signals.recv() => match signal {
+                Ok(Signal::Reload) => {
+                    info!("Received SIGHUP, reloading config");
+                    if let Some(reloaded) = reload_config(cli, log_state, &bots).await {
+                        config = reloaded;
+                    }
+                }
+                Ok(Signal::Shutdown) => {
+                    info!("Received shutdown signal, exiting");
+                    break;
+                }
+                Err(err) => {
+                    error!(?err, "Failed to receive signal, exiting");
+                    break;
+                }
+            },
+            _ = config_changed(&mut watcher) => {
+                info!("Config file changed, reloading config");
+                if let Some(reloaded) = reload_config(cli, log_state, &bots).await {
+                    config = reloaded;
+                }
             }
-            Err(err) => {
-                return Err(err);
+            _ = async { while tasks.join_next().await.is_some() {} } => {
+                info!("All bots have stopped, exiting");
+                break;
             }
-        };
+        }
+    }
 
-        info!(
-            "Successfully signed in as {} (ID: {})",
-            user.username().unwrap_or("<no username>"),
-            user.id()
+    // Let in-flight updates finish, but don't hang around forever for them
+    shutdown.send_replace(true);
+    let drain_timeout = config.drain_timeout();
+    let drained = tokio::time::timeout(drain_timeout, async {
+        while tasks.join_next().await.is_some() {}
+    })
+    .await;
+
+    if drained.is_err() {
+        warn!(
+            ?drain_timeout,
+            "Updates still being handled after drain timeout, aborting them"
         );
+        tasks.abort_all();
     }
 
-    let me = client.get_me().await.wrap_err("Failed to get self")?;
+    save_sessions(&bots)
+}
 
-    info!("Successfully connected and authorized");
+/// Saves the session of every bot, carrying on past failures so that as many
+/// sessions as possible are saved.
+fn save_sessions(bots: &[Arc<Bot>]) -> Result<()> {
+    info!("Saving session files");
+    let mut result = Ok(());
+
+    for bot in bots {
+        if let Err(err) = bot.session_file.save(bot.client().session()) {
+            let err = color_eyre::Report::new(err).wrap_err(format!(
+                "Failed to save session on exit for profile {}",
+                bot.profile.name()
+            ));
+            error!(?err, "Failed to save session");
+            result = Err(err);
+        }
+    }
 
-    let bot = Bot {
-        client: client.clone(),
-        me,
-    };
+    result
+}
 
-    println!("Press Ctrl+C to exit");
+async fn config_changed(watcher: &mut Option<ConfigWatcher>) {
+    match watcher {
+        Some(watcher) => watcher.changed().await,
+        None => std::future::pending().await,
+    }
+}
 
-    tokio::select! {
-        _ = signal::ctrl_c() => {
-            info!("Received SIGINT, exiting");
+/// Reads the config again and hands it to every bot, keeping the current one if
+/// it can't be loaded.
+///
+/// Connection settings of profiles only take effect after a restart.
+async fn reload_config(cli: &Cli, log_state: &LogState, bots: &[Arc<Bot>]) -> Option<Arc<Config>> {
+    let config = match Config::from_cli(cli) {
+        Ok(config) => Arc::new(config),
+        Err(err) => {
+            error!(?err, "Failed to reload config, keeping the current one");
+            return None;
         }
-        update_result = handle_updates(&bot) => {
-            match update_result {
-                Ok(_) => info!("Disconnected from Telegram gracefully"),
-                Err(e) => error!("Error while handling updates: {}", e),
+    };
+
+    if let Err(err) = log_state.set_level_filter(config.log_level().unwrap_or_default()) {
+        warn!(?err, "Failed to apply reloaded log level");
+    }
+
+    for bot in bots {
+        *bot.config.write().unwrap() = config.clone();
+
+        // Aliases added through the alias command keep taking precedence
+        let mut aliases = config.aliases().clone();
+        match bot.storage.aliases().await {
+            Ok(stored) => aliases.extend(stored),
+            Err(err) => {
+                warn!(
+                    ?err,
+                    profile = bot.profile.name(),
+                    "Failed to load aliases from storage, keeping the current ones"
+                );
+                continue;
             }
         }
+        bot.aliases.replace(aliases);
     }
 
-    info!("Saving session file and exiting");
-    client
-        .session()
-        .save_to_file(config.session_filename())
-        .wrap_err("Failed to save session on exit")?;
+    info!("Reloaded config");
+    Some(config)
+}
 
-    Ok(log_state)
+async fn connect_bot(
+    config: Arc<Config>,
+    profile: Profile,
+    commands: Arc<CommandRegistry>,
+) -> Result<Bot> {
+    let (client, session_file) = auth::connect(&profile).await?;
+
+    if !auth::is_authorized(&client).await? {
+        return Err(AuthError::NotAuthorized.into());
+    }
+
+    let storage = Storage::open(profile.storage_filename()).wrap_err("Failed to open storage")?;
+    let mut aliases = config.aliases().clone();
+    aliases.extend(
+        storage
+            .aliases()
+            .await
+            .wrap_err("Failed to load aliases from storage")?,
+    );
+
+    let me = client.get_me().await.wrap_err("Failed to get self")?;
+
+    info!("Successfully connected and authorized");
+
+    Ok(Bot {
+        limiter: RateLimiter::new(profile.rate_limits()),
+        own_dice: OwnDice::default(),
+        client: RwLock::new(client),
+        me,
+        aliases: AliasStore::new(aliases),
+        config: RwLock::new(config),
+        profile,
+        session_file,
+        commands,
+        scheduler: Scheduler::new(storage.clone()),
+        storage,
+    })
 }
 
-async fn handle_updates(bot: &Bot) -> Result<()> {
+/// Handles updates and sends scheduled jobs for a single bot until it quits or
+/// `shutdown` is set, reconnecting with backoff when the connection fails for
+/// a transient reason.
+async fn run_bot(bot: &Arc<Bot>, mut shutdown: watch::Receiver<bool>) {
+    let mut backoff = Backoff::default();
+
     loop {
-        let update = bot.client.next_update().await?;
-        match handle_update(bot, update).await {
-            Ok(quit) if quit => {
-                break;
+        let connected_at = Instant::now();
+        let client = bot.client();
+        let result = tokio::select! {
+            update_result = handle_updates(bot, &mut shutdown) => update_result,
+            _ = bot.scheduler.run(&client, &bot.limiter) => unreachable!("The scheduler never stops"),
+        };
+
+        let err = match result {
+            Ok(_) => {
+                info!("Disconnected from Telegram gracefully");
+                return;
             }
+            Err(err) => err,
+        };
+
+        if !is_transient(&err) {
+            error!(?err, "Stopping bot after a fatal error");
+            return;
+        }
+
+        warn!(?err, "Lost connection to Telegram");
+
+        if connected_at.elapsed() >= supervisor::STABLE_AFTER {
+            backoff.reset();
+        }
+
+        tokio::select! {
+            result = reconnect(bot, &mut backoff) => if let Err(err) = result {
+                error!(?err, "Stopping bot, unable to reconnect");
+                return;
+            },
+            _ = shutdown.wait_for(|&stop| stop) => return,
+        }
+    }
+}
+
+/// Checks whether an error was caused by something that reconnecting may fix.
+fn is_transient(err: &color_eyre::Report) -> bool {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<InvocationError>())
+        .is_some_and(|err| !supervisor::is_fatal(err))
+}
+
+/// Replaces the bot's client with a freshly connected one, saving the session
+/// and waiting before each attempt until one succeeds or fails fatally.
+async fn reconnect(bot: &Bot, backoff: &mut Backoff) -> Result<()> {
+    loop {
+        let old_client = bot.client();
+
+        if let Err(err) = bot.session_file.save(old_client.session()) {
+            warn!(?err, "Failed to save session before reconnecting");
+        }
+
+        let delay = backoff.next_delay();
+        info!(
+            attempt = backoff.attempt(),
+            ?delay,
+            "Reconnecting to Telegram"
+        );
+        tokio::time::sleep(delay).await;
+
+        let session = Session::load(&old_client.session().save()).map_err(SessionError::from)?;
+        let client = match auth::connect_with_session(&bot.profile, session).await {
+            Ok(client) => client,
             Err(err) => {
-                error!(?err, "Error handling update");
+                warn!(?err, "Failed to reconnect");
+                continue;
             }
-            Ok(_) => {}
+        };
+
+        match client.is_authorized().await {
+            Ok(true) => {
+                *bot.client.write().unwrap() = client;
+                info!("Reconnected to Telegram");
+                return Ok(());
+            }
+            Ok(false) => return Err(AuthError::NotAuthorized.into()),
+            Err(err) if supervisor::is_fatal(&err) => return Err(err.into()),
+            Err(err) => warn!(?err, "Failed to check authorization after reconnecting"),
         }
     }
+}
 
-    Ok(())
+/// Dispatches updates until one of them asks to quit or `shutdown` is set, then
+/// waits for the ones still being handled.
+async fn handle_updates(bot: &Arc<Bot>, shutdown: &mut watch::Receiver<bool>) -> Result<()> {
+    let client = bot.client();
+    let mut dispatcher = Dispatcher::new(bot.clone());
+
+    let result = loop {
+        tokio::select! {
+            update = client.next_update() => match update {
+                Ok(update) => dispatcher.dispatch(update),
+                Err(err) => break Err(err.into()),
+            },
+            quit = dispatcher.next_handled() => if quit {
+                break Ok(());
+            },
+            _ = shutdown.wait_for(|&stop| stop) => break Ok(()),
+        }
+    };
+
+    // Updates already being handled get to finish before shutting down or
+    // reconnecting, and are aborted if they take too long
+    let drain_timeout = bot.config().drain_timeout();
+    if tokio::time::timeout(drain_timeout, dispatcher.finish())
+        .await
+        .is_err()
+    {
+        warn!(
+            ?drain_timeout,
+            "Timed out waiting for updates being handled"
+        );
+    }
+
+    result
 }
 
-async fn handle_command(context: &Context) -> Result<bool> {
+async fn handle_command(context: &Context<'_>) -> Result<bool> {
     let result = command::parse_chat_command(context)
         .await
         .wrap_err("Failed to parse chat command")?;
 
-    match result.response {
+    apply_response(context, result.response).await;
+
+    Ok(result.quit)
+}
+
+async fn apply_response(context: &Context<'_>, response: Option<command::ActionResponse>) {
+    let limiter = context.bot.limiter();
+    let chat_id = context.chat.id();
+
+    match response {
         Some(command::ActionResponse::Delete) => {
-            if let Err(err) = context.message.delete().await {
+            if let Err(err) = limiter.call(chat_id, context.message.delete()).await {
                 error!(?err, "Failed to delete command message");
             }
         }
         Some(command::ActionResponse::Edit(new_message)) => {
-            if let Err(err) = context.message.edit(new_message).await {
+            if let Err(err) = limiter
+                .call(chat_id, context.message.edit(new_message))
+                .await
+            {
                 error!(?err, "Failed to edit command message");
             }
         }
         Some(command::ActionResponse::Reply(response)) => {
-            if let Err(err) = context.message.reply(response).await {
+            if let Err(err) = limiter.call(chat_id, context.message.reply(response)).await {
                 error!(?err, "Failed to reply to command message");
             }
         }
+        Some(command::ActionResponse::Replace(new_message)) => {
+            let reply_to = context.message.reply_to_message_id();
+            if let Err(err) = limiter.call(chat_id, context.message.delete()).await {
+                error!(?err, "Failed to delete command message");
+            }
+            let client = context.bot.client();
+            if let Err(err) = limiter
+                .call(
+                    chat_id,
+                    client.send_message(&context.chat, new_message.reply_to(reply_to)),
+                )
+                .await
+            {
+                error!(?err, "Failed to send replacement for command message");
+            }
+        }
         None => {}
     };
-
-    Ok(result.quit)
 }
 
-async fn handle_dice(
-    bot: &Bot,
-    context: &Context,
-    dice: &Dice,
-    predicate: fn(i32) -> bool,
-) -> Result<()> {
-    if predicate(dice.raw.value) {
+/// Rerolls a dice that doesn't match its rule by deleting it and sending a new
+/// one. The new dice comes back as an update of its own, so chains of rerolls
+/// continue one dice at a time, carrying the values rolled so far with them.
+async fn handle_dice(bot: &Bot, context: &Context<'_>, dice: &Dice, rule: &DiceRule) -> Result<()> {
+    let message = &context.message;
+    let chat_id = context.chat.id();
+
+    let mut values = match bot.own_dice.take(chat_id, message.id()) {
+        Some(OwnRoll::Reroll(values)) => values,
+        Some(OwnRoll::Command) => return Ok(()),
+        None => Vec::new(),
+    };
+    values.push(dice.raw.value);
+
+    let limits = bot.config().reroll();
+    let matched = rule.accepts(dice.raw.value);
+
+    if matched || values.len() >= limits.max_attempts as usize {
+        if !matched {
+            info!(
+                emoticon = dice.emoji(),
+                attempts = values.len(),
+                "Gave up rerolling dice"
+            );
+        }
+
+        if let Err(err) = bot
+            .storage
+            .record_dice_rolls(chat_id, dice.emoji(), &values, matched)
+            .await
+        {
+            warn!(?err, "Failed to record dice statistics");
+        }
+
         return Ok(());
     }
 
-    let message = &context.message;
-    let reply_to = message.reply_to_message_id();
+    tokio::time::sleep(limits.delay).await;
 
-    message
-        .delete()
+    bot.limiter
+        .call(chat_id, message.delete())
         .await
         .wrap_err("Failed to delete non-maxed dice")?;
 
@@ -216,51 +643,117 @@ async fn handle_dice(
     });
 
     let dice_msg = InputMessage::text("")
-        .reply_to(reply_to)
+        .reply_to(message.reply_to_message_id())
         .copy_media(&dice_media)
         .silent(true);
 
-    bot.client
-        .send_message(&context.chat, dice_msg)
+    let client = bot.client();
+    let sent = bot
+        .limiter
+        .call(chat_id, client.send_message(&context.chat, dice_msg))
         .await
         .wrap_err("Failed to send new dice media message")?;
 
+    // Updates in a chat are handled in order, so this is remembered before the
+    // new dice comes back.
+    bot.own_dice
+        .insert(chat_id, sent.id(), OwnRoll::Reroll(values));
+
     Ok(())
 }
 
-async fn handle_message(bot: &Bot, context: &Context) -> Result<bool> {
+/// Applies a substitution to the replied-to message, or else to the most recent
+/// of our own messages that it matches, and deletes the substitution message.
+async fn handle_sed(bot: &Bot, context: &Context<'_>, substitution: &Substitution) -> Result<()> {
     let message = &context.message;
+    let is_mine = |m: &Message| m.outgoing() || m.sender().is_some_and(|s| s.id() == bot.me.id());
 
-    if let Some(Media::Dice(ref dice)) = message.media() {
-        match dice.raw.emoticon.as_str() {
-            "🎲" => {
-                return handle_dice(bot, context, dice, |v| v == 6)
-                    .await
-                    .map(|_| false);
-            }
-            "🏀" => {
-                return handle_dice(bot, context, dice, |v| v >= 4)
-                    .await
-                    .map(|_| false);
-            }
-            "🎯" => {
-                return handle_dice(bot, context, dice, |v| v == 6)
-                    .await
-                    .map(|_| false);
-            }
-            "⚽" => {
-                return handle_dice(bot, context, dice, |v| v >= 3)
-                    .await
-                    .map(|_| false);
+    let target = match message
+        .get_reply()
+        .await
+        .wrap_err("Failed to get replied-to message")?
+    {
+        Some(reply) if is_mine(&reply) => {
+            substitution.apply(reply.text()).map(|text| (reply, text))
+        }
+        Some(_) => {
+            debug!("Replied-to message is not ours, ignoring substitution");
+            return Ok(());
+        }
+        None => {
+            let mut messages = bot
+                .client()
+                .iter_messages(&context.chat)
+                .offset_id(message.id())
+                .limit(SED_SEARCH_LIMIT);
+            let mut found = None;
+
+            while let Some(candidate) = messages
+                .next()
+                .await
+                .wrap_err("Failed to fetch previous messages")?
+            {
+                if !is_mine(&candidate) || Substitution::parse(candidate.text()).is_some() {
+                    continue;
+                }
+
+                if let Some(text) = substitution.apply(candidate.text()) {
+                    found = Some((candidate, text));
+                    break;
+                }
             }
-            "🎳" => {
-                return handle_dice(bot, context, dice, |v| v == 6)
-                    .await
-                    .map(|_| false);
+
+            found
+        }
+    };
+
+    let Some((target, text)) = target else {
+        debug!("No message matched substitution");
+        return Ok(());
+    };
+
+    bot.limiter
+        .call(context.chat.id(), target.edit(InputMessage::text(text)))
+        .await
+        .wrap_err("Failed to edit message with substitution")?;
+
+    bot.limiter
+        .call(context.chat.id(), message.delete())
+        .await
+        .wrap_err("Failed to delete substitution message")?;
+
+    Ok(())
+}
+
+async fn handle_message(bot: &Bot, context: &Context<'_>) -> Result<bool> {
+    let message = &context.message;
+
+    if message.media().is_none()
+        && let Some(substitution) = Substitution::parse(message.text().trim())
+    {
+        return handle_sed(bot, context, &substitution).await.map(|_| false);
+    }
+
+    if message.media().is_none() && message.text().contains('#') {
+        let response = command::expand_note_shorthand(context)
+            .await
+            .wrap_err("Failed to expand note references")?;
+
+        if response.is_some() {
+            apply_response(context, response).await;
+            return Ok(false);
+        }
+    }
+
+    if let Some(Media::Dice(ref dice)) = message.media() {
+        let config = bot.config();
+        match config.dice_rule(context.chat.id(), &dice.raw.emoticon) {
+            Some(rule) => {
+                return handle_dice(bot, context, dice, rule).await.map(|_| false);
             }
-            em => {
+            None => {
                 warn!(
-                    emoticon = em,
+                    emoticon = dice.raw.emoticon,
                     value = dice.raw.value,
                     "Unhandled dice message"
                 );
@@ -278,20 +771,33 @@ async fn handle_update(bot: &Bot, update: Update) -> Result<bool> {
             let text = message.text().trim();
 
             let context = Context {
+                bot,
                 chat: message.chat(),
                 message: message.clone(),
+                input: None,
             };
 
-            if text.starts_with('!') {
+            if command::strip_prefix(text, bot.config().prefixes(context.chat.id())).is_some() {
                 match handle_command(&context).await {
                     Err(err) => {
                         if let Some(clap_err) = err.root_cause().downcast_ref::<clap::Error>() {
                             let formatted = clap_err.to_string();
-                            if context.chat.id() == bot.me.id() {
-                                message.reply(formatted).await?;
+                            let chat_id = context.chat.id();
+                            if matches!(
+                                clap_err.kind(),
+                                ErrorKind::DisplayHelp
+                                    | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+                            ) {
+                                let help = command::help_message(&formatted);
+                                bot.limiter.call(chat_id, message.edit(help)).await?;
+                            } else if chat_id == bot.me.id() {
+                                bot.limiter.call(chat_id, message.reply(formatted)).await?;
                             } else {
-                                message.delete().await?;
-                                bot.client.send_message(&bot.me, formatted).await?;
+                                bot.limiter.call(chat_id, message.delete()).await?;
+                                let client = bot.client();
+                                bot.limiter
+                                    .call(bot.me.id(), client.send_message(&bot.me, formatted))
+                                    .await?;
                             }
                             Ok(false)
                         } else {
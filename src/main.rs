@@ -2,7 +2,7 @@ use std::{io, process::ExitCode};
 
 use color_eyre::Result;
 
-use shabby::run;
+use shabby::{AuthError, run};
 
 #[allow(
     unused_imports,
@@ -28,6 +28,11 @@ async fn main() -> Result<ExitCode> {
             };
         }
 
+        if let Some(AuthError::NotAuthorized) = err.downcast_ref::<AuthError>() {
+            eprintln!("Error: {}", err);
+            return Ok(ExitCode::from(77));
+        }
+
         eprintln!("Error: {:?}", err);
 
         for cause in err.chain() {
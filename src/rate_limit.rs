@@ -0,0 +1,175 @@
+use std::{collections::HashMap, future::Future, sync::Mutex, time::Duration};
+
+use grammers_client::InvocationError;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::config::{RateLimit, RateLimits};
+
+/// Spaces out outgoing requests so that they stay within per-chat and global budgets.
+///
+/// Requests are let through in the order they arrive. Flood waits short enough
+/// to be within the configured maximum are slept through and retried by the
+/// client itself, while any that still reach the limiter pause further
+/// requests to the affected chat until the wait is over.
+#[derive(Debug)]
+pub struct RateLimiter {
+    global: Mutex<Schedule>,
+    chats: Mutex<HashMap<i64, Schedule>>,
+    per_chat: RateLimit,
+}
+
+/// Tracks when the next request may be made under a single budget, allowing
+/// bursts of up to the budget's number of requests.
+#[derive(Debug, Clone, Copy)]
+struct Schedule {
+    interval: Duration,
+    tolerance: Duration,
+    next: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        Self {
+            global: Mutex::new(Schedule::new(limits.global, Instant::now())),
+            chats: Mutex::new(HashMap::new()),
+            per_chat: limits.per_chat,
+        }
+    }
+
+    /// Waits for a turn to make a request to `chat_id`, then makes it.
+    pub async fn call<T>(
+        &self,
+        chat_id: i64,
+        request: impl Future<Output = Result<T, InvocationError>>,
+    ) -> Result<T, InvocationError> {
+        wait_until(chat_id, self.reserve_chat(chat_id, Instant::now())).await;
+        wait_until(chat_id, self.reserve_global(Instant::now())).await;
+
+        let result = request.await;
+
+        if let Err(InvocationError::Rpc(err)) = &result
+            && err.is("FLOOD_WAIT")
+        {
+            let wait = Duration::from_secs(err.value.unwrap_or_default().into());
+            warn!(
+                chat_id,
+                ?wait,
+                "Flood wait exceeded threshold, pausing chat"
+            );
+            self.pause(chat_id, Instant::now() + wait);
+        }
+
+        result
+    }
+
+    fn reserve_chat(&self, chat_id: i64, now: Instant) -> Instant {
+        let mut chats = self.chats.lock().unwrap();
+        if !chats.contains_key(&chat_id) {
+            chats.retain(|_, schedule| schedule.next > now);
+        }
+
+        chats
+            .entry(chat_id)
+            .or_insert_with(|| Schedule::new(self.per_chat, now))
+            .reserve(now)
+    }
+
+    fn reserve_global(&self, now: Instant) -> Instant {
+        self.global.lock().unwrap().reserve(now)
+    }
+
+    fn pause(&self, chat_id: i64, until: Instant) {
+        let mut chats = self.chats.lock().unwrap();
+        chats
+            .entry(chat_id)
+            .or_insert_with(|| Schedule::new(self.per_chat, until))
+            .pause(until);
+    }
+}
+
+async fn wait_until(chat_id: i64, at: Instant) {
+    let now = Instant::now();
+    if at > now {
+        debug!(chat_id, wait = ?(at - now), "Rate limiting outgoing request");
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+impl Schedule {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        let requests = limit.requests.max(1);
+        let interval = limit.period / requests;
+
+        Self {
+            interval,
+            tolerance: interval * (requests - 1),
+            next: now,
+        }
+    }
+
+    /// Reserves a slot at or after `at`, returning when it is.
+    fn reserve(&mut self, at: Instant) -> Instant {
+        let allowed = self.next.checked_sub(self.tolerance).unwrap_or(self.next);
+        let slot = allowed.max(at);
+        self.next = self.next.max(at) + self.interval;
+        slot
+    }
+
+    /// Makes sure that no slot is handed out before `until`.
+    fn pause(&mut self, until: Instant) {
+        self.next = self.next.max(until + self.tolerance);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limit(requests: u32, seconds: u64) -> RateLimit {
+        RateLimit {
+            requests,
+            period: Duration::from_secs(seconds),
+        }
+    }
+
+    #[test]
+    fn test_schedule_allows_burst_then_spaces_out() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(limit(3, 3), now);
+
+        assert_eq!(schedule.reserve(now), now);
+        assert_eq!(schedule.reserve(now), now);
+        assert_eq!(schedule.reserve(now), now);
+        assert_eq!(schedule.reserve(now), now + Duration::from_secs(1));
+        assert_eq!(schedule.reserve(now), now + Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_schedule_pause() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(limit(5, 5), now);
+        schedule.pause(now + Duration::from_secs(10));
+
+        assert_eq!(schedule.reserve(now), now + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_limiter_applies_chat_and_global_budgets() {
+        let limiter = RateLimiter::new(&RateLimits {
+            global: limit(3, 3),
+            per_chat: limit(1, 2),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve_chat(1, now), now);
+        assert_eq!(limiter.reserve_chat(1, now), now + Duration::from_secs(2));
+        assert_eq!(limiter.reserve_chat(2, now), now);
+
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve_global(now), now);
+        }
+        assert_eq!(limiter.reserve_global(now), now + Duration::from_secs(1));
+    }
+}
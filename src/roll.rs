@@ -0,0 +1,613 @@
+use std::{
+    fmt::{self, Display},
+    iter::Peekable,
+    str::Chars,
+};
+
+use rand::Rng;
+use thiserror::Error;
+
+/// The most dice a single term can roll, not counting explosions.
+const MAX_DICE: u32 = 100;
+
+/// The most sides a die can have.
+const MAX_SIDES: u32 = 1000;
+
+/// The most extra dice explosions can add to a single term.
+const MAX_EXPLOSIONS: usize = 100;
+
+/// A parsed dice expression, such as `4d6kh3+2` or `2d20kl1-1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expression {
+    terms: Vec<(Sign, Term)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sign {
+    Plus,
+    Minus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    Constant(u32),
+    Dice(DiceTerm),
+}
+
+/// A group of identical dice, like `4d6kh3` or `3d10!`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiceTerm {
+    pub count: u32,
+    pub sides: Sides,
+    pub keep: Option<Keep>,
+    /// Whether dice that roll their highest value are rolled again.
+    pub explode: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sides {
+    Number(u32),
+    /// Fudge dice, which roll -1, 0 or +1.
+    Fudge,
+}
+
+/// Which of the rolled dice count towards the total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Highest(u32),
+    Lowest(u32),
+    DropHighest(u32),
+    DropLowest(u32),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RollError {
+    #[error("Expected dice notation like `4d6kh3+2`")]
+    Empty,
+
+    #[error("Unexpected `{0}` in dice notation")]
+    Unexpected(char),
+
+    #[error("Expected `+` or `-` between terms")]
+    MissingOperator,
+
+    #[error("Dice notation ended unexpectedly")]
+    UnexpectedEnd,
+
+    #[error("Number is too large")]
+    NumberTooLarge,
+
+    #[error("Expected between 1 and {MAX_DICE} dice")]
+    InvalidCount,
+
+    #[error("Expected dice with between 1 and {MAX_SIDES} sides")]
+    InvalidSides,
+
+    #[error("Only dice with at least two sides can explode")]
+    CannotExplode,
+
+    #[error("`{0}` can only be used once per dice")]
+    Duplicate(&'static str),
+}
+
+/// The result of rolling every term of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub terms: Vec<(Sign, TermOutcome)>,
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TermOutcome {
+    Constant(u32),
+    Dice { sides: Sides, dice: Vec<Die> },
+}
+
+/// A single rolled die.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Die {
+    pub value: i64,
+    /// Whether the die counts towards the total.
+    pub kept: bool,
+    /// Whether the die rolled its highest value and caused another roll.
+    pub exploded: bool,
+}
+
+/// Splits text into the expressions in it, which are separated by commas or by
+/// whitespace that isn't next to a `+` or `-`.
+pub fn split_expressions(s: &str) -> Vec<String> {
+    let is_operator = |c: Option<char>| matches!(c, Some('+' | '-'));
+    let mut expressions = Vec::new();
+
+    for part in s.split(',') {
+        let mut current = String::new();
+        for chunk in part.split_whitespace() {
+            if !current.is_empty()
+                && !is_operator(current.chars().last())
+                && !is_operator(chunk.chars().next())
+            {
+                expressions.push(std::mem::take(&mut current));
+            }
+            current.push_str(chunk);
+        }
+        // Empty parts are kept so that parsing them reports an error
+        expressions.push(current);
+    }
+
+    expressions
+}
+
+impl Expression {
+    /// Parses dice notation, ignoring case and whitespace around operators.
+    pub fn parse(s: &str) -> Result<Self, RollError> {
+        let mut normalized = String::new();
+        let mut spaced = false;
+        for c in s.chars().flat_map(char::to_lowercase) {
+            if c.is_whitespace() {
+                spaced = !normalized.is_empty();
+                continue;
+            }
+            if spaced && !matches!(c, '+' | '-') && !normalized.ends_with(['+', '-']) {
+                return Err(RollError::MissingOperator);
+            }
+            spaced = false;
+            normalized.push(c);
+        }
+        if normalized.is_empty() {
+            return Err(RollError::Empty);
+        }
+
+        let mut parser = Parser {
+            chars: normalized.chars().peekable(),
+        };
+        let mut sign = match parser.eat('-') {
+            true => Sign::Minus,
+            false => {
+                parser.eat('+');
+                Sign::Plus
+            }
+        };
+        let mut terms = Vec::new();
+
+        loop {
+            terms.push((sign, parser.term()?));
+            sign = match parser.chars.next() {
+                None => break,
+                Some('+') => Sign::Plus,
+                Some('-') => Sign::Minus,
+                Some(c) => return Err(RollError::Unexpected(c)),
+            };
+        }
+
+        Ok(Self { terms })
+    }
+
+    pub fn roll(&self, rng: &mut impl Rng) -> Outcome {
+        let terms = self
+            .terms
+            .iter()
+            .map(|(sign, term)| {
+                let outcome = match term {
+                    Term::Constant(n) => TermOutcome::Constant(*n),
+                    Term::Dice(dice) => TermOutcome::Dice {
+                        sides: dice.sides,
+                        dice: dice.roll(rng),
+                    },
+                };
+                (*sign, outcome)
+            })
+            .collect::<Vec<_>>();
+
+        let total = terms
+            .iter()
+            .map(|(sign, outcome)| match sign {
+                Sign::Plus => outcome.value(),
+                Sign::Minus => -outcome.value(),
+            })
+            .sum();
+
+        Outcome { terms, total }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        self.chars.next_if_eq(&c).is_some()
+    }
+
+    fn unexpected(&mut self) -> RollError {
+        match self.chars.next() {
+            Some(c) => RollError::Unexpected(c),
+            None => RollError::UnexpectedEnd,
+        }
+    }
+
+    fn number(&mut self) -> Result<Option<u32>, RollError> {
+        let mut number: Option<u32> = None;
+
+        while let Some(digit) = self.chars.peek().and_then(|c| c.to_digit(10)) {
+            self.chars.next();
+            number = number
+                .unwrap_or(0)
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(digit))
+                .map(Some)
+                .ok_or(RollError::NumberTooLarge)?;
+        }
+
+        Ok(number)
+    }
+
+    fn required_number(&mut self) -> Result<u32, RollError> {
+        match self.number()? {
+            Some(n) => Ok(n),
+            None => Err(self.unexpected()),
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, RollError> {
+        let count = self.number()?;
+        if !self.eat('d') {
+            return match count {
+                Some(n) => Ok(Term::Constant(n)),
+                None => Err(self.unexpected()),
+            };
+        }
+
+        let count = count.unwrap_or(1);
+        if !(1..=MAX_DICE).contains(&count) {
+            return Err(RollError::InvalidCount);
+        }
+
+        let sides = if self.eat('f') {
+            Sides::Fudge
+        } else if self.eat('%') {
+            Sides::Number(100)
+        } else {
+            match self.required_number()? {
+                n @ 1..=MAX_SIDES => Sides::Number(n),
+                _ => return Err(RollError::InvalidSides),
+            }
+        };
+
+        let mut dice = DiceTerm {
+            count,
+            sides,
+            keep: None,
+            explode: false,
+        };
+
+        loop {
+            if self.eat('!') {
+                if dice.explode {
+                    return Err(RollError::Duplicate("!"));
+                }
+                if !matches!(sides, Sides::Number(2..)) {
+                    return Err(RollError::CannotExplode);
+                }
+                dice.explode = true;
+                continue;
+            }
+
+            let keep = if self.eat('k') {
+                match self.eat('l') {
+                    true => Keep::Lowest(self.required_number()?),
+                    false => {
+                        self.eat('h');
+                        Keep::Highest(self.required_number()?)
+                    }
+                }
+            } else if self.eat('d') {
+                match self.eat('h') {
+                    true => Keep::DropHighest(self.required_number()?),
+                    false => {
+                        self.eat('l');
+                        Keep::DropLowest(self.required_number()?)
+                    }
+                }
+            } else {
+                break;
+            };
+
+            if dice.keep.replace(keep).is_some() {
+                return Err(RollError::Duplicate("keep or drop"));
+            }
+        }
+
+        Ok(Term::Dice(dice))
+    }
+}
+
+impl DiceTerm {
+    fn roll(&self, rng: &mut impl Rng) -> Vec<Die> {
+        let mut dice = Vec::new();
+        let mut explosions = 0;
+
+        for _ in 0..self.count {
+            loop {
+                let value = self.sides.roll(rng);
+                let exploded =
+                    self.explode && value == self.sides.max() && explosions < MAX_EXPLOSIONS;
+                dice.push(Die {
+                    value,
+                    kept: true,
+                    exploded,
+                });
+
+                if !exploded {
+                    break;
+                }
+                explosions += 1;
+            }
+        }
+
+        let len = dice.len();
+        let (lowest, highest) = match self.keep {
+            None => (0, 0),
+            Some(Keep::Highest(n)) => (len.saturating_sub(n as usize), 0),
+            Some(Keep::Lowest(n)) => (0, len.saturating_sub(n as usize)),
+            Some(Keep::DropHighest(n)) => (0, len.min(n as usize)),
+            Some(Keep::DropLowest(n)) => (len.min(n as usize), 0),
+        };
+
+        let mut order = (0..len).collect::<Vec<_>>();
+        order.sort_by_key(|&i| dice[i].value);
+        for &i in order[..lowest].iter().chain(&order[len - highest..]) {
+            dice[i].kept = false;
+        }
+
+        dice
+    }
+}
+
+impl Sides {
+    fn roll(self, rng: &mut impl Rng) -> i64 {
+        match self {
+            Sides::Number(sides) => rng.random_range(1..=sides as i64),
+            Sides::Fudge => rng.random_range(-1..=1),
+        }
+    }
+
+    fn max(self) -> i64 {
+        match self {
+            Sides::Number(sides) => sides as i64,
+            Sides::Fudge => 1,
+        }
+    }
+}
+
+impl TermOutcome {
+    pub fn value(&self) -> i64 {
+        match self {
+            TermOutcome::Constant(n) => *n as i64,
+            TermOutcome::Dice { dice, .. } => dice.iter().filter(|d| d.kept).map(|d| d.value).sum(),
+        }
+    }
+}
+
+impl Outcome {
+    /// Formats every die rolled as markdown, with dropped dice in italics and
+    /// exploded dice marked with `!`, followed by the total.
+    pub fn breakdown(&self) -> String {
+        let mut out = String::new();
+
+        for (i, (sign, term)) in self.terms.iter().enumerate() {
+            match (i, sign) {
+                (0, Sign::Plus) => {}
+                (0, Sign::Minus) => out.push('-'),
+                (_, Sign::Plus) => out.push_str(" + "),
+                (_, Sign::Minus) => out.push_str(" - "),
+            }
+
+            match term {
+                TermOutcome::Constant(n) => out.push_str(&n.to_string()),
+                TermOutcome::Dice { sides, dice } => {
+                    let dice = dice
+                        .iter()
+                        .map(|die| {
+                            let value = match (sides, die.value) {
+                                (Sides::Fudge, 1) => "+".to_string(),
+                                (Sides::Fudge, -1) => "-".to_string(),
+                                _ => die.value.to_string(),
+                            };
+                            let exploded = if die.exploded { "!" } else { "" };
+                            match die.kept {
+                                true => format!("{}{}", value, exploded),
+                                false => format!("_{}{}_", value, exploded),
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    out.push_str(&format!("[{}]", dice.join(", ")));
+                }
+            }
+        }
+
+        format!("{} = **{}**", out, self.total)
+    }
+}
+
+/// Formats the expression in its canonical notation.
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (sign, term)) in self.terms.iter().enumerate() {
+            match (i, sign) {
+                (0, Sign::Plus) => {}
+                (_, Sign::Plus) => write!(f, "+")?,
+                (_, Sign::Minus) => write!(f, "-")?,
+            }
+
+            match term {
+                Term::Constant(n) => write!(f, "{}", n)?,
+                Term::Dice(dice) => write!(f, "{}", dice)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for DiceTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d", self.count)?;
+        match self.sides {
+            Sides::Number(sides) => write!(f, "{}", sides)?,
+            Sides::Fudge => write!(f, "F")?,
+        }
+        if self.explode {
+            write!(f, "!")?;
+        }
+        match self.keep {
+            Some(Keep::Highest(n)) => write!(f, "kh{}", n),
+            Some(Keep::Lowest(n)) => write!(f, "kl{}", n),
+            Some(Keep::DropHighest(n)) => write!(f, "dh{}", n),
+            Some(Keep::DropLowest(n)) => write!(f, "dl{}", n),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let expression = Expression::parse("4d6kh3 + 2").unwrap();
+        assert_eq!(
+            expression.terms,
+            [
+                (
+                    Sign::Plus,
+                    Term::Dice(DiceTerm {
+                        count: 4,
+                        sides: Sides::Number(6),
+                        keep: Some(Keep::Highest(3)),
+                        explode: false,
+                    })
+                ),
+                (Sign::Plus, Term::Constant(2)),
+            ]
+        );
+
+        for (input, canonical) in [
+            ("d20", "1d20"),
+            ("2D20KL1-1", "2d20kl1-1"),
+            ("4d6d1", "4d6dl1"),
+            ("3d10!k2", "3d10!kh2"),
+            ("4dF", "4dF"),
+            ("d%", "1d100"),
+            ("-1+d4", "-1+1d4"),
+        ] {
+            assert_eq!(
+                Expression::parse(input).unwrap().to_string(),
+                canonical,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_expressions() {
+        assert_eq!(split_expressions("d20 d20"), ["d20", "d20"]);
+        assert_eq!(split_expressions("4d6 2"), ["4d6", "2"]);
+        assert_eq!(split_expressions("4d6 + 2 -1, d8"), ["4d6+2-1", "d8"]);
+        assert_eq!(split_expressions("d4,"), ["d4", ""]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Expression::parse(" "), Err(RollError::Empty));
+        assert_eq!(Expression::parse("4d"), Err(RollError::UnexpectedEnd));
+        assert_eq!(Expression::parse("4d6x"), Err(RollError::Unexpected('x')));
+        assert_eq!(Expression::parse("4d6+"), Err(RollError::UnexpectedEnd));
+        assert_eq!(
+            Expression::parse("d20 d20"),
+            Err(RollError::MissingOperator)
+        );
+        assert_eq!(Expression::parse("4d6 2"), Err(RollError::MissingOperator));
+        assert_eq!(Expression::parse("0d6"), Err(RollError::InvalidCount));
+        assert_eq!(Expression::parse("1000d6"), Err(RollError::InvalidCount));
+        assert_eq!(Expression::parse("1d0"), Err(RollError::InvalidSides));
+        assert_eq!(Expression::parse("1d1!"), Err(RollError::CannotExplode));
+        assert_eq!(Expression::parse("4dF!"), Err(RollError::CannotExplode));
+        assert_eq!(
+            Expression::parse("4d6kh3kl1"),
+            Err(RollError::Duplicate("keep or drop"))
+        );
+        assert_eq!(
+            Expression::parse("99999999999"),
+            Err(RollError::NumberTooLarge)
+        );
+    }
+
+    #[test]
+    fn test_roll_keeps_highest() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let outcome = Expression::parse("4d6kh3+2").unwrap().roll(&mut rng);
+
+        let TermOutcome::Dice { dice, .. } = &outcome.terms[0].1 else {
+            panic!("expected dice");
+        };
+        let mut values = dice.iter().map(|d| d.value).collect::<Vec<_>>();
+        values.sort();
+
+        assert_eq!(dice.len(), 4);
+        assert_eq!(dice.iter().filter(|d| d.kept).count(), 3);
+        assert_eq!(outcome.total, values[1..].iter().sum::<i64>() + 2);
+    }
+
+    #[test]
+    fn test_roll_is_reproducible() {
+        let expression = Expression::parse("10d6!dl2 - 1d4 + 3dF").unwrap();
+        let first = expression.roll(&mut StdRng::seed_from_u64(42));
+        let second = expression.roll(&mut StdRng::seed_from_u64(42));
+        assert_eq!(first, second);
+
+        let TermOutcome::Dice { dice, .. } = &first.terms[2].1 else {
+            panic!("expected dice");
+        };
+        assert!(dice.iter().all(|d| (-1..=1).contains(&d.value)));
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let die = |value, kept, exploded| Die {
+            value,
+            kept,
+            exploded,
+        };
+        let outcome = Outcome {
+            terms: vec![
+                (
+                    Sign::Plus,
+                    TermOutcome::Dice {
+                        sides: Sides::Number(6),
+                        dice: vec![
+                            die(6, true, true),
+                            die(5, true, false),
+                            die(2, false, false),
+                            die(4, true, false),
+                        ],
+                    },
+                ),
+                (
+                    Sign::Minus,
+                    TermOutcome::Dice {
+                        sides: Sides::Fudge,
+                        dice: vec![die(1, true, false), die(-1, true, false)],
+                    },
+                ),
+                (Sign::Plus, TermOutcome::Constant(2)),
+            ],
+            total: 17,
+        };
+
+        assert_eq!(outcome.breakdown(), "[6!, 5, _2_, 4] - [+, -] + 2 = **17**");
+    }
+}
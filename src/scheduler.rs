@@ -0,0 +1,158 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use grammers_client::{Client, InputMessage, InvocationError, types::PackedChat};
+use tokio::sync::Notify;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    rate_limit::RateLimiter,
+    storage::{Job, JobKind, Storage, StorageError},
+};
+
+/// How long to wait before trying again after a job fails to send or storage fails.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Sends stored jobs (reminders and scheduled messages) when they are due.
+pub struct Scheduler {
+    storage: Storage,
+    notify: Notify,
+}
+
+impl Scheduler {
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            notify: Notify::new(),
+        }
+    }
+
+    /// Stores a new job and wakes the scheduler so it is taken into account.
+    pub async fn add(&self, job: Job) -> Result<i64, StorageError> {
+        let id = self.storage.add_job(job).await?;
+        self.notify.notify_one();
+        Ok(id)
+    }
+
+    /// Removes a job, as long as it would be sent to the given chat.
+    pub async fn cancel(&self, chat_id: i64, id: i64) -> Result<bool, StorageError> {
+        if !self.pending(chat_id).await?.iter().any(|job| job.id == id) {
+            return Ok(false);
+        }
+
+        let removed = self.storage.remove_job(id).await?;
+        self.notify.notify_one();
+        Ok(removed)
+    }
+
+    /// Gets the pending jobs that will be sent to the given chat.
+    pub async fn pending(&self, chat_id: i64) -> Result<Vec<Job>, StorageError> {
+        let mut jobs = self.storage.jobs().await?;
+        jobs.retain(|job| PackedChat::from_bytes(&job.chat).is_ok_and(|chat| chat.id == chat_id));
+        Ok(jobs)
+    }
+
+    /// Sends jobs through `client` as they become due. Never returns, retrying
+    /// after a delay when sending a job or accessing storage fails.
+    pub async fn run(&self, client: &Client, limiter: &RateLimiter) {
+        info!("Starting scheduler");
+
+        loop {
+            let next = match self.send_due(client, limiter).await {
+                Ok(next) => next,
+                Err(err) => {
+                    error!(?err, "Failed to access scheduled jobs, retrying later");
+                    Some(unix_now().saturating_add(RETRY_DELAY.as_secs() as i64))
+                }
+            };
+
+            match next {
+                Some(due_at) => {
+                    let delay =
+                        Duration::from_secs(due_at.saturating_sub(unix_now()).max(0) as u64);
+                    debug!(?delay, "Waiting for next job");
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = self.notify.notified() => {}
+                    }
+                }
+                None => {
+                    debug!("No jobs pending, waiting for new ones");
+                    self.notify.notified().await;
+                }
+            }
+        }
+    }
+
+    /// Sends every job that is due, returning when the next one will be.
+    ///
+    /// Jobs that fail to send for a reason that may pass are kept and moved to
+    /// a later time, while jobs that can never be sent are dropped.
+    async fn send_due(
+        &self,
+        client: &Client,
+        limiter: &RateLimiter,
+    ) -> Result<Option<i64>, StorageError> {
+        for job in self.storage.due_jobs(unix_now()).await? {
+            let Ok(chat) = PackedChat::from_bytes(&job.chat) else {
+                error!(id = job.id, "Dropping scheduled job with an invalid chat");
+                self.storage.remove_job(job.id).await?;
+                continue;
+            };
+
+            debug!(id = job.id, "Sending scheduled job");
+            let text = match job.kind {
+                JobKind::Reminder => format!("⏰ {}", job.text),
+                JobKind::Scheduled => job.text.clone(),
+            };
+            let message = InputMessage::text(text).reply_to(job.reply_to);
+            let result = limiter
+                .call(chat.id, client.send_message(chat, message))
+                .await;
+
+            match result.map_err(|err| (retry_delay(&err), err)) {
+                Ok(_) => {
+                    self.storage.remove_job(job.id).await?;
+                }
+                Err((Some(delay), err)) => {
+                    warn!(
+                        ?err,
+                        id = job.id,
+                        ?delay,
+                        "Failed to send scheduled job, retrying later"
+                    );
+                    let due_at = unix_now().saturating_add(delay.as_secs() as i64);
+                    self.storage.reschedule_job(job.id, due_at).await?;
+                }
+                Err((None, err)) => {
+                    error!(
+                        ?err,
+                        id = job.id,
+                        "Failed to send scheduled job, dropping it"
+                    );
+                    self.storage.remove_job(job.id).await?;
+                }
+            }
+        }
+
+        self.storage.next_job_due_at().await
+    }
+}
+
+/// Decides how long to wait before sending a job again after `err`, or `None`
+/// if Telegram rejected it in a way that retrying won't change.
+fn retry_delay(err: &InvocationError) -> Option<Duration> {
+    match err {
+        InvocationError::Rpc(rpc) if rpc.is("FLOOD_WAIT") => {
+            Some(RETRY_DELAY.max(Duration::from_secs(rpc.value.unwrap_or_default().into())))
+        }
+        InvocationError::Rpc(rpc) => (rpc.code >= 500).then_some(RETRY_DELAY),
+        InvocationError::Dropped | InvocationError::Read(_) => Some(RETRY_DELAY),
+    }
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,210 @@
+use regex::{Regex, RegexBuilder};
+
+/// Characters that can separate the parts of a substitution.
+const DELIMITERS: [char; 3] = ['/', '|', '#'];
+
+/// A parsed `s/pattern/replacement/flags` substitution.
+#[derive(Debug)]
+pub struct Substitution {
+    regex: Regex,
+    replacement: String,
+    global: bool,
+    occurrence: usize,
+}
+
+impl Substitution {
+    /// Parses a sed-style substitution command.
+    ///
+    /// The delimiter is the character after the `s` and may be one of
+    /// [`DELIMITERS`]. Only `/` may leave out the closing delimiter. Returns
+    /// `None` if `text` is not a valid substitution, so that ordinary messages
+    /// are left alone.
+    pub fn parse(text: &str) -> Option<Self> {
+        let rest = text.strip_prefix('s')?;
+        let delimiter = rest.chars().next()?;
+        if !DELIMITERS.contains(&delimiter) {
+            return None;
+        }
+
+        let parts = split_unescaped(&rest[1..], delimiter);
+        let (pattern, replacement, flags) = match parts.as_slice() {
+            [pattern, replacement] if delimiter == '/' => (pattern, replacement, ""),
+            [pattern, replacement, flags] => (pattern, replacement, flags.as_str()),
+            _ => return None,
+        };
+
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let mut case_insensitive = false;
+        let mut global = false;
+        let mut occurrence = String::new();
+
+        for flag in flags.chars() {
+            match flag {
+                'i' | 'I' => case_insensitive = true,
+                'g' => global = true,
+                '0'..='9' => occurrence.push(flag),
+                _ => return None,
+            }
+        }
+
+        let occurrence = match occurrence.as_str() {
+            "" => 1,
+            n => n.parse().ok().filter(|&n| n > 0)?,
+        };
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .ok()?;
+
+        Some(Self {
+            regex,
+            replacement: convert_replacement(replacement),
+            global,
+            occurrence,
+        })
+    }
+
+    /// Applies the substitution, returning `None` if nothing was replaced.
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let mut result = String::with_capacity(text.len());
+        let mut last_end = 0;
+        let mut replaced = false;
+
+        for (index, captures) in self.regex.captures_iter(text).enumerate() {
+            let occurrence = index + 1;
+            if occurrence < self.occurrence {
+                continue;
+            }
+
+            let whole = captures.get(0).unwrap();
+            result.push_str(&text[last_end..whole.start()]);
+            captures.expand(&self.replacement, &mut result);
+            last_end = whole.end();
+            replaced = true;
+
+            if !self.global {
+                break;
+            }
+        }
+
+        if !replaced {
+            return None;
+        }
+
+        result.push_str(&text[last_end..]);
+        Some(result)
+    }
+}
+
+/// Splits `text` on `delimiter`, treating `\<delimiter>` as a literal delimiter.
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        let current = parts.last_mut().unwrap();
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => current.push(next),
+                Some(next) => {
+                    current.push('\\');
+                    current.push(next);
+                }
+                None => current.push('\\'),
+            },
+            c if c == delimiter => parts.push(String::new()),
+            c => current.push(c),
+        }
+    }
+
+    parts
+}
+
+/// Converts sed replacement syntax (`&`, `\1`) into the syntax used by [`regex`].
+fn convert_replacement(replacement: &str) -> String {
+    let mut result = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => {
+                    result.push_str("${");
+                    result.push(d);
+                    result.push('}');
+                }
+                Some('n') => result.push('\n'),
+                Some('$') => result.push_str("$$"),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            },
+            '&' => result.push_str("${0}"),
+            '$' => result.push_str("$$"),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sed(command: &str, text: &str) -> Option<String> {
+        Substitution::parse(command).unwrap().apply(text)
+    }
+
+    #[test]
+    fn test_first_occurrence_by_default() {
+        assert_eq!(sed("s/teh/the/", "teh teh").unwrap(), "the teh");
+        assert_eq!(sed("s/teh/the", "teh teh").unwrap(), "the teh");
+    }
+
+    #[test]
+    fn test_flags() {
+        assert_eq!(sed("s/teh/the/g", "teh teh").unwrap(), "the the");
+        assert_eq!(sed("s/TEH/the/gi", "teh Teh").unwrap(), "the the");
+        assert_eq!(sed("s/a/x/2", "aaaa").unwrap(), "axaa");
+        assert_eq!(sed("s/a/x/2g", "aaaa").unwrap(), "axxx");
+        assert!(Substitution::parse("s/a/b/q").is_none());
+        assert!(Substitution::parse("s/a/b/0").is_none());
+    }
+
+    #[test]
+    fn test_replacement_syntax() {
+        assert_eq!(
+            sed(r"s/(\w+) (\w+)/\2 \1/", "hello world").unwrap(),
+            "world hello"
+        );
+        assert_eq!(sed("s/o/[&]/g", "foo").unwrap(), "f[o][o]");
+        assert_eq!(sed(r"s/x/\&$1/", "x").unwrap(), "&$1");
+    }
+
+    #[test]
+    fn test_delimiters() {
+        assert_eq!(sed(r"s/a\/b/c/", "a/b").unwrap(), "c");
+        assert_eq!(sed("s|/|-|g", "a/b/c").unwrap(), "a-b-c");
+        assert!(Substitution::parse("sa/b/").is_none());
+        assert!(Substitution::parse("some text").is_none());
+        assert!(Substitution::parse("s#a#b").is_none());
+        assert_eq!(sed("s#a#b#", "a").unwrap(), "b");
+    }
+
+    #[test]
+    fn test_ordinary_text() {
+        assert!(Substitution::parse("s.t. we can").is_none());
+        assert!(Substitution::parse("s'up, what's up?").is_none());
+        assert!(Substitution::parse("s|t| we can").is_none());
+        assert!(Substitution::parse("s-o-s").is_none());
+    }
+
+    #[test]
+    fn test_no_match() {
+        assert!(sed("s/x/y/", "abc").is_none());
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, rand_core::RngCore},
+};
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, bail},
+};
+use grammers_client::session::{self as grammers_session, Session};
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::config::Profile;
+
+/// Marks a session file as encrypted, followed by the salt, nonce and ciphertext.
+const MAGIC: &[u8] = b"SHABBY-SESSION-1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Failed to access session file")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to load session")]
+    Load(#[from] grammers_session::Error),
+
+    #[error("Encrypted session file is truncated or corrupt")]
+    Malformed,
+
+    #[error("Failed to derive session key from passphrase")]
+    KeyDerivation,
+
+    #[error("Failed to decrypt session, is the passphrase correct?")]
+    Decrypt,
+
+    #[error("Failed to encrypt session")]
+    Encrypt,
+}
+
+/// A session file on disk, encrypted at rest if a passphrase is known for it.
+#[derive(Debug)]
+pub struct SessionFile {
+    path: PathBuf,
+    passphrase: Option<String>,
+}
+
+impl SessionFile {
+    /// Loads the session of a profile, or creates a new one if there is no file yet.
+    ///
+    /// An encrypted file is decrypted with the profile's configured passphrase,
+    /// prompting for it if none is configured. A configured passphrase also
+    /// means that a plaintext file gets encrypted the next time it is saved.
+    pub fn open(profile: &Profile) -> Result<(Self, Session)> {
+        let path = profile.session_filename().to_path_buf();
+
+        if !path.exists() {
+            debug!(path = %path.display(), "No session file, creating a new session");
+            let file = Self {
+                path,
+                passphrase: profile.session_passphrase()?,
+            };
+
+            return Ok((file, Session::new()));
+        }
+
+        let data = fs::read(&path)
+            .wrap_err_with(|| format!("Failed to read session file: {}", path.display()))?;
+
+        let (passphrase, data) = match is_encrypted(&data) {
+            true => {
+                debug!("Session file is encrypted");
+                let passphrase = match profile.session_passphrase()? {
+                    Some(passphrase) => passphrase,
+                    None => prompt_passphrase("Enter the session passphrase: ")?,
+                };
+                let data = decrypt(&data, &passphrase)?;
+                (Some(passphrase), data)
+            }
+            false => (profile.session_passphrase()?, data),
+        };
+
+        let session = Session::load(&data).map_err(SessionError::from)?;
+
+        Ok((Self { path, passphrase }, session))
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    /// Saves the session, encrypting it if a passphrase is known.
+    pub fn save(&self, session: &Session) -> Result<(), SessionError> {
+        let data = match &self.passphrase {
+            Some(passphrase) => encrypt(&session.save(), passphrase)?,
+            None => session.save(),
+        };
+
+        write_private(&self.path, &data)
+    }
+
+    pub fn remove(&self) -> Result<(), SessionError> {
+        Ok(fs::remove_file(&self.path)?)
+    }
+}
+
+/// Encrypts an existing plaintext session file in place.
+pub fn encrypt_file(profile: &Profile) -> Result<()> {
+    let path = profile.session_filename();
+    let data = fs::read(path)
+        .wrap_err_with(|| format!("Failed to read session file: {}", path.display()))?;
+
+    if is_encrypted(&data) {
+        println!("Session file is already encrypted: {}", path.display());
+        return Ok(());
+    }
+
+    let passphrase = match profile.session_passphrase()? {
+        Some(passphrase) => passphrase,
+        None => {
+            let passphrase = prompt_passphrase("Enter a new session passphrase: ")?;
+            if passphrase != prompt_passphrase("Repeat the passphrase: ")? {
+                bail!("Passphrases do not match");
+            }
+            passphrase
+        }
+    };
+
+    // Make sure the file really is a session before locking it away
+    Session::load(&data).map_err(SessionError::from)?;
+    write_private(path, &encrypt(&data, &passphrase)?)?;
+
+    info!(path = %path.display(), "Encrypted session file");
+    println!("Encrypted session file: {}", path.display());
+
+    Ok(())
+}
+
+/// Decrypts an encrypted session file in place.
+pub fn decrypt_file(profile: &Profile) -> Result<()> {
+    let path = profile.session_filename();
+    let data = fs::read(path)
+        .wrap_err_with(|| format!("Failed to read session file: {}", path.display()))?;
+
+    if !is_encrypted(&data) {
+        println!("Session file is not encrypted: {}", path.display());
+        return Ok(());
+    }
+
+    let passphrase = match profile.session_passphrase()? {
+        Some(passphrase) => passphrase,
+        None => prompt_passphrase("Enter the session passphrase: ")?,
+    };
+
+    write_private(path, &decrypt(&data, &passphrase)?)?;
+
+    info!(path = %path.display(), "Decrypted session file");
+    println!("Decrypted session file: {}", path.display());
+
+    Ok(())
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, SessionError> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(&nonce, data)
+        .map_err(|_| SessionError::Encrypt)?;
+
+    let mut result = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    result.extend_from_slice(MAGIC);
+    result.extend_from_slice(&salt);
+    result.extend_from_slice(&nonce);
+    result.extend_from_slice(&ciphertext);
+
+    Ok(result)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, SessionError> {
+    let data = data.strip_prefix(MAGIC).ok_or(SessionError::Malformed)?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(SessionError::Malformed);
+    }
+
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    cipher(passphrase, salt)?
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| SessionError::Decrypt)
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, SessionError> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| SessionError::KeyDerivation)?;
+
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+/// Writes through a temporary file readable only by the owner, so that a
+/// failed write never leaves a truncated session behind.
+fn write_private(path: &Path, data: &[u8]) -> Result<(), SessionError> {
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp_path = PathBuf::from(temp_name);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+fn prompt_passphrase(prompt: &str) -> Result<String> {
+    rpassword::prompt_password(prompt).wrap_err("Failed to read passphrase")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let encrypted = encrypt(b"auth key", "hunter2").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt(&encrypted, "hunter2").unwrap(), b"auth key");
+    }
+
+    #[test]
+    fn test_decrypt_rejects_wrong_passphrase_and_tampering() {
+        let mut encrypted = encrypt(b"auth key", "hunter2").unwrap();
+        assert!(matches!(
+            decrypt(&encrypted, "hunter3"),
+            Err(SessionError::Decrypt)
+        ));
+
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(matches!(
+            decrypt(&encrypted, "hunter2"),
+            Err(SessionError::Decrypt)
+        ));
+
+        assert!(matches!(
+            decrypt(MAGIC, "hunter2"),
+            Err(SessionError::Malformed)
+        ));
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+
+#[cfg(unix)]
+use tokio::signal::unix::{self, SignalKind};
+
+/// What the process has been asked to do by a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGINT or SIGTERM, save everything and exit.
+    Shutdown,
+    /// SIGHUP, reload the config file.
+    Reload,
+}
+
+/// Listens for the signals that shabby reacts to.
+pub struct Signals {
+    #[cfg(unix)]
+    terminate: unix::Signal,
+    #[cfg(unix)]
+    hangup: unix::Signal,
+}
+
+impl Signals {
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            terminate: unix::signal(SignalKind::terminate())?,
+            #[cfg(unix)]
+            hangup: unix::signal(SignalKind::hangup())?,
+        })
+    }
+
+    #[cfg(unix)]
+    pub async fn recv(&mut self) -> io::Result<Signal> {
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|_| Signal::Shutdown),
+            _ = self.terminate.recv() => Ok(Signal::Shutdown),
+            _ = self.hangup.recv() => Ok(Signal::Reload),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub async fn recv(&mut self) -> io::Result<Signal> {
+        tokio::signal::ctrl_c().await.map(|_| Signal::Shutdown)
+    }
+}
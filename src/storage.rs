@@ -0,0 +1,89 @@
+use std::{
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use rusqlite::Connection;
+use thiserror::Error;
+use tracing::info;
+
+pub use self::{
+    dice::DiceAttempts,
+    jobs::{Job, JobKind},
+    notes::{MediaSource, Note},
+};
+
+mod aliases;
+mod dice;
+mod jobs;
+mod migrations;
+mod notes;
+
+/// Persistent bot state, kept in an SQLite database.
+///
+/// Cloning is cheap and all clones share the same connection.
+#[derive(Clone, Debug)]
+pub struct Storage {
+    connection: Arc<Mutex<Connection>>,
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Failed to create storage directory")]
+    Io(#[from] io::Error),
+
+    #[error("Database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("Database version {0} is newer than supported version {1}")]
+    UnsupportedVersion(usize, usize),
+
+    #[error("Storage task failed")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+impl Storage {
+    /// Opens (creating if needed) the database at `path` and applies any pending migrations.
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        if let Some(dir) = path.parent()
+            && dir.to_string_lossy() != ""
+            && !dir.exists()
+        {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        info!(path = %path.display(), "Opening storage");
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, StorageError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, StorageError> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrations::run(&mut connection)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `f` with the database connection on the blocking thread pool.
+    pub async fn call<F, T>(&self, f: F) -> Result<T, StorageError>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut connection)
+        })
+        .await??;
+
+        Ok(result)
+    }
+}
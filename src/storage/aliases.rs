@@ -0,0 +1,61 @@
+use rusqlite::params;
+
+use super::{Storage, StorageError};
+
+impl Storage {
+    pub async fn aliases(&self) -> Result<Vec<(String, String)>, StorageError> {
+        self.call(|conn| {
+            conn.prepare("SELECT name, expansion FROM aliases ORDER BY name")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn set_alias(&self, name: &str, expansion: &str) -> Result<(), StorageError> {
+        let (name, expansion) = (name.to_string(), expansion.to_string());
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO aliases (name, expansion) VALUES (?1, ?2)
+                 ON CONFLICT (name) DO UPDATE SET expansion = excluded.expansion",
+                params![name, expansion],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_alias(&self, name: &str) -> Result<bool, StorageError> {
+        let name = name.to_string();
+        let removed = self
+            .call(move |conn| conn.execute("DELETE FROM aliases WHERE name = ?1", [name]))
+            .await?;
+
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_alias_roundtrip() {
+        let storage = Storage::open_in_memory().unwrap();
+        storage.set_alias("cr", "case r").await.unwrap();
+        storage.set_alias("cr", "case r $@").await.unwrap();
+        storage.set_alias("cu", "case u").await.unwrap();
+
+        assert_eq!(
+            storage.aliases().await.unwrap(),
+            [
+                ("cr".to_string(), "case r $@".to_string()),
+                ("cu".to_string(), "case u".to_string())
+            ]
+        );
+
+        assert!(storage.remove_alias("cr").await.unwrap());
+        assert!(!storage.remove_alias("cr").await.unwrap());
+    }
+}
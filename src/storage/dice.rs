@@ -0,0 +1,153 @@
+use rusqlite::params;
+
+use super::{Storage, StorageError};
+
+/// How many attempts it took to reroll dice of one emoticon until they matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiceAttempts {
+    pub emoticon: String,
+    /// Dice that were checked against their rule.
+    pub rolls: i64,
+    /// Every roll made for them, counting the original ones.
+    pub attempts: i64,
+    /// The most attempts a single dice took.
+    pub most_attempts: i64,
+    /// Dice that still didn't match when the attempts ran out.
+    pub gave_up: i64,
+}
+
+impl Storage {
+    /// Records the values rolled for one dice, in order, and whether the last
+    /// of them matched its rule.
+    pub async fn record_dice_rolls(
+        &self,
+        chat_id: i64,
+        emoticon: &str,
+        values: &[i32],
+        matched: bool,
+    ) -> Result<(), StorageError> {
+        let (emoticon, values) = (emoticon.to_string(), values.to_vec());
+        self.call(move |conn| {
+            let transaction = conn.transaction()?;
+
+            for value in &values {
+                transaction.execute(
+                    "INSERT INTO dice_values (chat_id, emoticon, value, count) VALUES (?1, ?2, ?3, 1)
+                     ON CONFLICT (chat_id, emoticon, value) DO UPDATE SET count = count + 1",
+                    params![chat_id, emoticon, value],
+                )?;
+            }
+
+            transaction.execute(
+                "INSERT INTO dice_rerolls (chat_id, emoticon, rolls, attempts, most_attempts, gave_up)
+                 VALUES (?1, ?2, 1, ?3, ?3, ?4)
+                 ON CONFLICT (chat_id, emoticon) DO UPDATE SET
+                     rolls = rolls + 1,
+                     attempts = attempts + excluded.attempts,
+                     most_attempts = max(most_attempts, excluded.most_attempts),
+                     gave_up = gave_up + excluded.gave_up",
+                params![chat_id, emoticon, values.len() as i64, !matched as i64],
+            )?;
+
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Gets reroll attempts per emoticon, in one chat or summed over all of them.
+    pub async fn dice_attempts(
+        &self,
+        chat_id: Option<i64>,
+    ) -> Result<Vec<DiceAttempts>, StorageError> {
+        self.call(move |conn| {
+            conn.prepare(
+                "SELECT emoticon, sum(rolls), sum(attempts), max(most_attempts), sum(gave_up)
+                 FROM dice_rerolls WHERE ?1 IS NULL OR chat_id = ?1
+                 GROUP BY emoticon ORDER BY emoticon",
+            )?
+            .query_map([chat_id], |row| {
+                Ok(DiceAttempts {
+                    emoticon: row.get(0)?,
+                    rolls: row.get(1)?,
+                    attempts: row.get(2)?,
+                    most_attempts: row.get(3)?,
+                    gave_up: row.get(4)?,
+                })
+            })?
+            .collect()
+        })
+        .await
+    }
+
+    /// Gets how often each value of an emoticon was rolled, in one chat or
+    /// summed over all of them.
+    pub async fn dice_values(
+        &self,
+        chat_id: Option<i64>,
+        emoticon: &str,
+    ) -> Result<Vec<(i32, i64)>, StorageError> {
+        let emoticon = emoticon.to_string();
+        self.call(move |conn| {
+            conn.prepare(
+                "SELECT value, sum(count) FROM dice_values
+                 WHERE (?1 IS NULL OR chat_id = ?1) AND emoticon = ?2
+                 GROUP BY value ORDER BY value",
+            )?
+            .query_map(params![chat_id, emoticon], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dice_stats() {
+        let storage = Storage::open_in_memory().unwrap();
+        storage
+            .record_dice_rolls(1, "🎲", &[6], true)
+            .await
+            .unwrap();
+        storage
+            .record_dice_rolls(1, "🎲", &[2, 3, 6], true)
+            .await
+            .unwrap();
+        storage
+            .record_dice_rolls(2, "🎲", &[1, 1], false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.dice_attempts(Some(1)).await.unwrap(),
+            [DiceAttempts {
+                emoticon: "🎲".to_string(),
+                rolls: 2,
+                attempts: 4,
+                most_attempts: 3,
+                gave_up: 0,
+            }]
+        );
+        assert_eq!(
+            storage.dice_attempts(None).await.unwrap(),
+            [DiceAttempts {
+                emoticon: "🎲".to_string(),
+                rolls: 3,
+                attempts: 6,
+                most_attempts: 3,
+                gave_up: 1,
+            }]
+        );
+
+        assert_eq!(
+            storage.dice_values(None, "🎲").await.unwrap(),
+            [(1, 2), (2, 1), (3, 1), (6, 2)]
+        );
+        assert_eq!(storage.dice_values(Some(2), "🎲").await.unwrap(), [(1, 2)]);
+        assert!(storage.dice_values(None, "🎯").await.unwrap().is_empty());
+    }
+}
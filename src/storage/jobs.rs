@@ -0,0 +1,165 @@
+use rusqlite::{Row, params};
+
+use super::{Storage, StorageError};
+
+/// A message waiting to be sent by the scheduler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub id: i64,
+    pub kind: JobKind,
+    /// A serialized `PackedChat` to send the message to.
+    pub chat: Vec<u8>,
+    pub reply_to: Option<i32>,
+    /// The text as written, without the ⏰ that reminders are sent with.
+    pub text: String,
+    /// When to send the message, in seconds since the Unix epoch.
+    pub due_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobKind {
+    Reminder,
+    Scheduled,
+}
+
+impl JobKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::Reminder => "reminder",
+            JobKind::Scheduled => "scheduled",
+        }
+    }
+}
+
+impl Job {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let kind: String = row.get("kind")?;
+
+        Ok(Self {
+            id: row.get("id")?,
+            kind: match kind.as_str() {
+                "reminder" => JobKind::Reminder,
+                _ => JobKind::Scheduled,
+            },
+            chat: row.get("chat")?,
+            reply_to: row.get("reply_to")?,
+            text: row.get("text")?,
+            due_at: row.get("due_at")?,
+        })
+    }
+}
+
+const SELECT_JOBS: &str = "SELECT id, kind, chat, reply_to, text, due_at FROM jobs";
+
+impl Storage {
+    /// Adds a job, ignoring its `id`, and returns the ID it was stored with.
+    pub async fn add_job(&self, job: Job) -> Result<i64, StorageError> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO jobs (kind, chat, reply_to, text, due_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    job.kind.as_str(),
+                    job.chat,
+                    job.reply_to,
+                    job.text,
+                    job.due_at
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    pub async fn jobs(&self) -> Result<Vec<Job>, StorageError> {
+        self.call(|conn| {
+            conn.prepare(&format!("{SELECT_JOBS} ORDER BY due_at, id"))?
+                .query_map([], Job::from_row)?
+                .collect()
+        })
+        .await
+    }
+
+    /// Gets the jobs that are due at or before `now`.
+    pub async fn due_jobs(&self, now: i64) -> Result<Vec<Job>, StorageError> {
+        self.call(move |conn| {
+            conn.prepare(&format!(
+                "{SELECT_JOBS} WHERE due_at <= ?1 ORDER BY due_at, id"
+            ))?
+            .query_map([now], Job::from_row)?
+            .collect()
+        })
+        .await
+    }
+
+    /// Gets when the next job is due, if there are any.
+    pub async fn next_job_due_at(&self) -> Result<Option<i64>, StorageError> {
+        self.call(|conn| conn.query_row("SELECT MIN(due_at) FROM jobs", [], |row| row.get(0)))
+            .await
+    }
+
+    /// Moves a job to a new due time, such as after it failed to send.
+    pub async fn reschedule_job(&self, id: i64, due_at: i64) -> Result<bool, StorageError> {
+        let updated = self
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE jobs SET due_at = ?2 WHERE id = ?1",
+                    params![id, due_at],
+                )
+            })
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    pub async fn remove_job(&self, id: i64) -> Result<bool, StorageError> {
+        let removed = self
+            .call(move |conn| conn.execute("DELETE FROM jobs WHERE id = ?1", [id]))
+            .await?;
+
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn job(due_at: i64) -> Job {
+        Job {
+            id: 0,
+            kind: JobKind::Reminder,
+            chat: vec![0; 17],
+            reply_to: Some(1),
+            text: "check the deploy".to_string(),
+            due_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_due_jobs() {
+        let storage = Storage::open_in_memory().unwrap();
+        assert_eq!(storage.next_job_due_at().await.unwrap(), None);
+
+        let late = storage.add_job(job(200)).await.unwrap();
+        let early = storage.add_job(job(100)).await.unwrap();
+
+        assert_eq!(storage.next_job_due_at().await.unwrap(), Some(100));
+        assert_eq!(
+            storage
+                .due_jobs(150)
+                .await
+                .unwrap()
+                .iter()
+                .map(|j| j.id)
+                .collect::<Vec<_>>(),
+            [early]
+        );
+
+        assert!(storage.reschedule_job(early, 300).await.unwrap());
+        assert_eq!(storage.next_job_due_at().await.unwrap(), Some(200));
+
+        assert!(storage.remove_job(early).await.unwrap());
+        assert_eq!(storage.jobs().await.unwrap()[0].id, late);
+    }
+}
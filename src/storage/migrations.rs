@@ -0,0 +1,102 @@
+use rusqlite::Connection;
+use tracing::{debug, info};
+
+use super::StorageError;
+
+/// Schema migrations, applied in order. The database's `user_version` records
+/// how many have been applied, so existing entries must never be changed.
+const MIGRATIONS: &[&str] = &[
+    // 1: runtime command aliases
+    "CREATE TABLE aliases (
+        name TEXT PRIMARY KEY NOT NULL,
+        expansion TEXT NOT NULL
+    );",
+    // 2: notes
+    "CREATE TABLE notes (
+        name TEXT PRIMARY KEY NOT NULL,
+        text TEXT NOT NULL,
+        markdown TEXT NOT NULL,
+        media_chat BLOB,
+        media_message_id INTEGER
+    );",
+    // 3: reminders and scheduled messages
+    "CREATE TABLE jobs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        kind TEXT NOT NULL,
+        chat BLOB NOT NULL,
+        reply_to INTEGER,
+        text TEXT NOT NULL,
+        due_at INTEGER NOT NULL
+    );
+    CREATE INDEX jobs_due_at ON jobs (due_at);",
+    // 4: dice reroll statistics
+    "CREATE TABLE dice_values (
+        chat_id INTEGER NOT NULL,
+        emoticon TEXT NOT NULL,
+        value INTEGER NOT NULL,
+        count INTEGER NOT NULL,
+        PRIMARY KEY (chat_id, emoticon, value)
+    );
+    CREATE TABLE dice_rerolls (
+        chat_id INTEGER NOT NULL,
+        emoticon TEXT NOT NULL,
+        rolls INTEGER NOT NULL,
+        attempts INTEGER NOT NULL,
+        most_attempts INTEGER NOT NULL,
+        gave_up INTEGER NOT NULL,
+        PRIMARY KEY (chat_id, emoticon)
+    );",
+];
+
+pub fn run(connection: &mut Connection) -> Result<(), StorageError> {
+    let version =
+        connection.pragma_query_value(None, "user_version", |row| row.get::<_, i64>(0))?;
+    let version = usize::try_from(version).unwrap_or_default();
+
+    if version > MIGRATIONS.len() {
+        return Err(StorageError::UnsupportedVersion(version, MIGRATIONS.len()));
+    }
+
+    debug!(version, "Current database version");
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let target = index + 1;
+        info!(version = target, "Applying database migration");
+
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", target as i64)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_migrations_apply_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        run(&mut connection).unwrap();
+        run(&mut connection).unwrap();
+
+        let version: i64 = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)
+            .unwrap();
+        assert!(matches!(
+            run(&mut connection),
+            Err(StorageError::UnsupportedVersion(_, _))
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use rusqlite::{OptionalExtension, Row, params};
+
+use super::{Storage, StorageError};
+
+/// A saved snippet of text, optionally referring to a message whose media it includes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub name: String,
+    pub text: String,
+    pub markdown: String,
+    pub media: Option<MediaSource>,
+}
+
+/// Where to fetch a note's media from, as the media itself can't be stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaSource {
+    /// A serialized `PackedChat`.
+    pub chat: Vec<u8>,
+    pub message_id: i32,
+}
+
+impl Note {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let chat: Option<Vec<u8>> = row.get("media_chat")?;
+        let message_id: Option<i32> = row.get("media_message_id")?;
+
+        Ok(Self {
+            name: row.get("name")?,
+            text: row.get("text")?,
+            markdown: row.get("markdown")?,
+            media: chat
+                .zip(message_id)
+                .map(|(chat, message_id)| MediaSource { chat, message_id }),
+        })
+    }
+}
+
+impl Storage {
+    pub async fn note(&self, name: &str) -> Result<Option<Note>, StorageError> {
+        let name = name.to_string();
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT name, text, markdown, media_chat, media_message_id
+                 FROM notes WHERE name = ?1",
+                [name],
+                Note::from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    /// Gets the notes whose names are in `names`, skipping names without a note.
+    pub async fn notes_named(&self, names: Vec<String>) -> Result<Vec<Note>, StorageError> {
+        self.call(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT name, text, markdown, media_chat, media_message_id
+                 FROM notes WHERE name = ?1",
+            )?;
+
+            let mut notes = Vec::new();
+            for name in names {
+                if let Some(note) = statement.query_row([name], Note::from_row).optional()? {
+                    notes.push(note);
+                }
+            }
+
+            Ok(notes)
+        })
+        .await
+    }
+
+    pub async fn note_names(&self) -> Result<Vec<String>, StorageError> {
+        self.call(|conn| {
+            conn.prepare("SELECT name FROM notes ORDER BY name")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn save_note(&self, note: Note) -> Result<(), StorageError> {
+        self.call(move |conn| {
+            let (chat, message_id) = note.media.map(|m| (m.chat, m.message_id)).unzip();
+
+            conn.execute(
+                "INSERT INTO notes (name, text, markdown, media_chat, media_message_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (name) DO UPDATE SET
+                     text = excluded.text,
+                     markdown = excluded.markdown,
+                     media_chat = excluded.media_chat,
+                     media_message_id = excluded.media_message_id",
+                params![note.name, note.text, note.markdown, chat, message_id],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_note(&self, name: &str) -> Result<bool, StorageError> {
+        let name = name.to_string();
+        let removed = self
+            .call(move |conn| conn.execute("DELETE FROM notes WHERE name = ?1", [name]))
+            .await?;
+
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_note_roundtrip() {
+        let storage = Storage::open_in_memory().unwrap();
+        let note = Note {
+            name: "todo".to_string(),
+            text: "buy milk".to_string(),
+            markdown: "**buy** milk".to_string(),
+            media: Some(MediaSource {
+                chat: vec![1; 17],
+                message_id: 42,
+            }),
+        };
+
+        storage.save_note(note.clone()).await.unwrap();
+        assert_eq!(storage.note("todo").await.unwrap(), Some(note));
+        assert_eq!(storage.note_names().await.unwrap(), ["todo"]);
+        assert_eq!(
+            storage
+                .notes_named(vec!["nope".to_string(), "todo".to_string()])
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert!(storage.remove_note("todo").await.unwrap());
+        assert_eq!(storage.note("todo").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use grammers_client::InvocationError;
+use rand::Rng;
+
+/// Delay before the first reconnection attempt.
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between reconnection attempts.
+const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// How long a connection has to stay up for the backoff to start over.
+pub const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Jittered exponential backoff between reconnection attempts.
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(INITIAL_DELAY, MAX_DELAY)
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            attempt: 0,
+        }
+    }
+
+    /// How many delays have been handed out since the last reset.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Gets the delay before the next attempt.
+    ///
+    /// The ceiling doubles with every attempt up to the maximum, and the actual
+    /// delay is picked at random between half the ceiling and the ceiling so
+    /// that several bots don't all reconnect at the same moment.
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let ceiling_ms = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::rng().random_range(ceiling_ms / 2..=ceiling_ms))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Checks whether an error means that reconnecting is pointless, such as the
+/// session having been revoked or used from somewhere else at the same time.
+pub fn is_fatal(err: &InvocationError) -> bool {
+    match err {
+        InvocationError::Rpc(rpc) => rpc.code == 401 || rpc.is("AUTH_KEY_DUPLICATED"),
+        InvocationError::Dropped | InvocationError::Read(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_within_bounds_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(10));
+
+        for ceiling in [1, 2, 4, 8, 10, 10] {
+            let delay = backoff.next_delay();
+            let ceiling = Duration::from_secs(ceiling);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{delay:?}");
+        }
+
+        backoff.reset();
+        assert_eq!(backoff.attempt(), 0);
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+}